@@ -0,0 +1,330 @@
+use chrono::{
+    DateTime, Datelike, Duration, Local, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Timelike,
+    Utc, Weekday,
+};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+
+use crate::pattern::{self, Pattern};
+
+/// A period during which pushes to some branches are refused, as written
+/// in the configuration. Every condition given must hold.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Blackout {
+    pub name: Option<String>,
+    /// Comma-separated branch patterns
+    pub branches: String,
+    /// Weekdays, e.g. `["fri", "sat", "sun"]`
+    pub days: Option<Vec<String>>,
+    /// Time of day range, e.g. `16:00-24:00`
+    pub hours: Option<String>,
+    /// Days or inclusive day ranges, e.g. `["2026-11-03", "2026-12-24..2026-12-26"]`
+    pub dates: Option<Vec<String>>,
+    /// IANA timezone, the system's if unset
+    pub timezone: Option<String>,
+}
+
+/// A blackout with its conditions parsed.
+pub struct Window {
+    pub label: String,
+    branches: Vec<Pattern>,
+    days: Option<Vec<Weekday>>,
+    /// Start and end, in seconds since midnight
+    hours: Option<(u32, u32)>,
+    dates: Option<Vec<(NaiveDate, NaiveDate)>>,
+    timezone: Option<Tz>,
+}
+
+/// How far ahead to look for the end of a window: up to three boundaries
+/// a day (midnight and both ends of `hours`) for three years.
+const MAX_LOOKAHEAD: usize = 3 * 366 * 3;
+
+impl Window {
+    pub fn compile(blackout: &Blackout, idx: usize) -> Result<Self, String> {
+        let label = blackout
+            .name
+            .clone()
+            .unwrap_or_else(|| format!("blackout #{}", idx + 1));
+        let in_window = |e: String| format!("{}: {}", label, e);
+
+        let branches = pattern::split_list(&blackout.branches)
+            .iter()
+            .map(|branch| Pattern::parse(branch))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(in_window)?;
+
+        let days = blackout
+            .days
+            .as_ref()
+            .map(|days| {
+                days.iter()
+                    .map(|day| {
+                        day.parse::<Weekday>()
+                            .map_err(|_| format!("invalid day '{}'", day))
+                    })
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .transpose()
+            .map_err(in_window)?;
+
+        let hours = blackout
+            .hours
+            .as_deref()
+            .map(parse_hours)
+            .transpose()
+            .map_err(in_window)?;
+
+        let dates = blackout
+            .dates
+            .as_ref()
+            .map(|dates| dates.iter().map(|d| parse_dates(d)).collect())
+            .transpose()
+            .map_err(in_window)?;
+
+        let timezone = blackout
+            .timezone
+            .as_deref()
+            .map(|tz| {
+                tz.parse::<Tz>()
+                    .map_err(|_| format!("unknown timezone '{}'", tz))
+            })
+            .transpose()
+            .map_err(in_window)?;
+
+        if days.is_none() && hours.is_none() && dates.is_none() {
+            return Err(in_window("needs days, hours or dates".to_string()));
+        }
+
+        Ok(Self {
+            label,
+            branches,
+            days,
+            hours,
+            dates,
+            timezone,
+        })
+    }
+
+    pub fn applies_to(&self, branch: &str) -> bool {
+        self.branches.iter().any(|pattern| pattern.matches(branch))
+    }
+
+    /// Whether the window is in effect at `now` and, if so, when it ends.
+    /// `Some(None)` for a window that doesn't end in the foreseeable future.
+    pub fn active_until(&self, now: DateTime<Utc>) -> Option<Option<DateTime<Utc>>> {
+        let mut local = self.to_local(now);
+        if !self.is_active(local) {
+            return None;
+        }
+
+        for _ in 0..MAX_LOOKAHEAD {
+            local = self.next_boundary(local);
+            if !self.is_active(local) {
+                return Some(Some(self.to_utc(local)));
+            }
+        }
+        Some(None)
+    }
+
+    fn is_active(&self, local: NaiveDateTime) -> bool {
+        let date = local.date();
+        let seconds = local.time().num_seconds_from_midnight();
+
+        self.days
+            .as_ref()
+            .is_none_or(|days| days.contains(&date.weekday()))
+            && self
+                .hours
+                .is_none_or(|(start, end)| start <= seconds && seconds < end)
+            && self.dates.as_ref().is_none_or(|dates| {
+                dates
+                    .iter()
+                    .any(|(first, last)| *first <= date && date <= *last)
+            })
+    }
+
+    /// The next instant after `local` at which the window may open or close.
+    fn next_boundary(&self, local: NaiveDateTime) -> NaiveDateTime {
+        let midnight = local.date().and_time(NaiveTime::MIN);
+        let next_midnight = midnight + Duration::days(1);
+
+        let mut candidates = vec![next_midnight];
+        if let Some((start, end)) = self.hours {
+            candidates.push(midnight + Duration::seconds(start.into()));
+            candidates.push(midnight + Duration::seconds(end.into()));
+        }
+
+        candidates
+            .into_iter()
+            .filter(|candidate| *candidate > local)
+            .min()
+            .unwrap_or(next_midnight)
+    }
+
+    fn to_local(&self, time: DateTime<Utc>) -> NaiveDateTime {
+        match &self.timezone {
+            Some(tz) => time.with_timezone(tz).naive_local(),
+            None => time.with_timezone(&Local).naive_local(),
+        }
+    }
+
+    fn to_utc(&self, local: NaiveDateTime) -> DateTime<Utc> {
+        // Times skipped by a DST change are taken as UTC
+        match &self.timezone {
+            Some(tz) => tz
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc)),
+            None => Local
+                .from_local_datetime(&local)
+                .earliest()
+                .map(|time| time.with_timezone(&Utc)),
+        }
+        .unwrap_or_else(|| Utc.from_utc_datetime(&local))
+    }
+}
+
+/// `HH:MM-HH:MM`, the end exclusive and at most `24:00`.
+fn parse_hours(hours: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("invalid hours '{}', expected HH:MM-HH:MM", hours);
+    let (start, end) = hours.split_once('-').ok_or_else(invalid)?;
+    let start = parse_time(start.trim()).ok_or_else(invalid)?;
+    let end = parse_time(end.trim()).ok_or_else(invalid)?;
+
+    if start >= end {
+        return Err(format!(
+            "hours '{}' must not cross midnight, use two windows",
+            hours
+        ));
+    }
+    Ok((start, end))
+}
+
+/// Seconds since midnight of `HH:MM`, `24:00` included.
+fn parse_time(time: &str) -> Option<u32> {
+    if time == "24:00" {
+        return Some(24 * 3600);
+    }
+    NaiveTime::parse_from_str(time, "%H:%M")
+        .ok()
+        .map(|time| time.num_seconds_from_midnight())
+}
+
+/// `YYYY-MM-DD` or `YYYY-MM-DD..YYYY-MM-DD`.
+fn parse_dates(dates: &str) -> Result<(NaiveDate, NaiveDate), String> {
+    let parse = |date: &str| {
+        NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("invalid date '{}', expected YYYY-MM-DD", date.trim()))
+    };
+
+    match dates.split_once("..") {
+        Some((first, last)) => Ok((parse(first)?, parse(last)?)),
+        None => {
+            let date = parse(dates)?;
+            Ok((date, date))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window(toml: &str) -> Window {
+        let blackout: Blackout = toml::from_str(toml).unwrap();
+        Window::compile(&blackout, 0).unwrap()
+    }
+
+    fn at(datetime: &str) -> DateTime<Utc> {
+        datetime.parse().unwrap()
+    }
+
+    #[test]
+    fn friday_afternoon() {
+        let friday = window(
+            r#"
+            branches = "main"
+            days = ["fri"]
+            hours = "16:00-24:00"
+            timezone = "Europe/Paris"
+            "#,
+        );
+
+        // Friday 2026-10-16, Paris is UTC+2
+        let cases = [
+            ("2026-10-16T13:59:00Z", None),
+            ("2026-10-16T14:00:00Z", Some(Some("2026-10-16T22:00:00Z"))),
+            ("2026-10-16T21:59:59Z", Some(Some("2026-10-16T22:00:00Z"))),
+            ("2026-10-16T22:00:00Z", None),
+            ("2026-10-15T15:00:00Z", None),
+        ];
+
+        for (now, expected) in cases {
+            assert_eq!(
+                friday.active_until(at(now)),
+                expected.map(|end| end.map(at)),
+                "{}",
+                now
+            );
+        }
+        assert!(friday.applies_to("main"));
+        assert!(!friday.applies_to("develop"));
+    }
+
+    #[test]
+    fn date_ranges_span_days() {
+        let release = window(
+            r#"
+            branches = "main,release/*"
+            dates = ["2026-12-24..2026-12-26", "2026-11-03"]
+            timezone = "UTC"
+            "#,
+        );
+
+        assert_eq!(
+            release.active_until(at("2026-12-24T09:00:00Z")),
+            Some(Some(at("2026-12-27T00:00:00Z")))
+        );
+        assert_eq!(
+            release.active_until(at("2026-11-03T23:00:00Z")),
+            Some(Some(at("2026-11-04T00:00:00Z")))
+        );
+        assert_eq!(release.active_until(at("2026-11-04T00:00:00Z")), None);
+        assert!(release.applies_to("release/1.2"));
+    }
+
+    #[test]
+    fn weekend_runs_until_monday() {
+        let weekend = window(
+            r#"
+            branches = "*"
+            days = ["sat", "sun"]
+            timezone = "UTC"
+            "#,
+        );
+
+        assert_eq!(
+            weekend.active_until(at("2026-10-17T10:00:00Z")),
+            Some(Some(at("2026-10-19T00:00:00Z")))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_windows() {
+        for toml in [
+            r#"branches = "main""#,
+            r#"branches = "main"
+               hours = "22:00-06:00""#,
+            r#"branches = "main"
+               days = ["friyay"]"#,
+            r#"branches = "main"
+               dates = ["2026-13-01"]"#,
+            r#"branches = "main"
+               hours = "9-17""#,
+        ] {
+            let blackout: Blackout = toml::from_str(toml).unwrap();
+            assert!(Window::compile(&blackout, 0).is_err(), "{}", toml);
+        }
+    }
+}
@@ -0,0 +1,1003 @@
+use regex::Regex;
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+use crate::blackout::Blackout;
+use crate::git;
+use crate::remote::RemoteUrl;
+use crate::rules::{Action, Rule};
+use crate::status::StatusPolicy;
+
+/// Keys accepted under `safe-push.*` in git config (compared lowercased).
+const GIT_CONFIG_KEYS: &[&str] = &[
+    "token",
+    "gitlabUrl",
+    "blockingStage",
+    "blockingJobs",
+    "blockingEnvironments",
+    "preBlockDuration",
+    "postBlockDuration",
+    "checkInterval",
+    "minCheckInterval",
+    "maxCheckInterval",
+    "maxPages",
+    "timeout",
+    "etaPipelines",
+    "freezeBranches",
+    "freezeAction",
+    "failedBranch",
+    "failedBranchJobs",
+    "manualJobs",
+    "mergeRequestPipelines",
+    "mergeTrainPipelines",
+    "mergeTrain",
+    "simpleMode",
+];
+
+/// Keys a committed `.gitlab-safe-push.toml` may not set: they decide where
+/// the token is sent.
+const REPO_FORBIDDEN_KEYS: &[&str] = &["token", "gitlab_url", "profiles"];
+
+/// Why a configuration source could not be used.
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The file exists but could not be read
+    Io { path: PathBuf, source: io::Error },
+    /// The file is not valid JSON/TOML or does not match the expected shape
+    Parse {
+        path: PathBuf,
+        line: usize,
+        column: usize,
+        message: String,
+        suggestion: Option<String>,
+    },
+    /// A file sets `rules` alongside a key they replace
+    Conflict { path: PathBuf, message: String },
+    /// A `safe-push.*` git config entry is unknown or has a bad value
+    GitConfig {
+        key: String,
+        message: String,
+        suggestion: Option<String>,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Io { path, source } => {
+                write!(f, "unable to read {}: {}", path.display(), source)
+            }
+            ConfigError::Parse {
+                path,
+                line,
+                column,
+                message,
+                suggestion,
+            } => {
+                write!(f, "{}:{}:{}: {}", path.display(), line, column, message)?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, " (did you mean `{}`?)", suggestion)?;
+                }
+                Ok(())
+            }
+            ConfigError::Conflict { path, message } => {
+                write!(f, "{}: {}", path.display(), message)
+            }
+            ConfigError::GitConfig {
+                key,
+                message,
+                suggestion,
+            } => {
+                write!(f, "git config {}: {}", key, message)?;
+                if let Some(suggestion) = suggestion {
+                    write!(f, " (did you mean `safe-push.{}`?)", suggestion)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Settings shared by every configuration layer.
+///
+/// Layers are merged field by field, highest precedence first:
+/// CLI flags, environment variables, `git config safe-push.*`,
+/// `.gitlab-safe-push.toml` at the repository root (no credentials or
+/// endpoints), the profile matching the remote host, then
+/// `~/.gitlab-safe-push-config.json`. A matching profile's `token` and
+/// `gitlab_url` rank right below CLI flags.
+#[derive(Serialize, Deserialize, Default, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub token: Option<String>,
+    pub gitlab_url: Option<String>,
+    pub blocking_stage: Option<String>,
+    pub blocking_jobs: Option<String>,
+    pub blocking_environments: Option<String>,
+    pub pre_block_duration: Option<u64>,
+    pub post_block_duration: Option<u64>,
+    pub check_interval: Option<u64>,
+    /// Shortest delay between checks while waiting, 5s by default
+    pub min_check_interval: Option<u64>,
+    /// Longest delay between checks while waiting, 300s by default
+    pub max_check_interval: Option<u64>,
+    pub max_pages: Option<u32>,
+    /// Longest `--wait`, as a duration such as `15m` or `1h 30m`
+    pub timeout: Option<String>,
+    /// Successful pipelines whose job durations estimate when blocking jobs
+    /// end, 5 by default, 0 to disable
+    pub eta_pipelines: Option<u32>,
+    pub simple_mode: Option<bool>,
+    /// Blocking rules, evaluated in order; replace the simple/advanced modes
+    pub rules: Option<Vec<Rule>>,
+    /// Other refs and projects whose running pipelines also block the push
+    pub watch: Option<Vec<WatchTarget>>,
+    /// Branch patterns deploy freeze periods apply to
+    pub freeze_branches: Option<String>,
+    /// What a deploy freeze does to those branches, `block` by default
+    pub freeze_action: Option<Action>,
+    /// Local periods during which pushes to some branches are refused
+    pub blackouts: Option<Vec<Blackout>>,
+    /// What a failed latest pipeline on the pushed branch does, `allow` by default
+    pub failed_branch: Option<Action>,
+    /// Job patterns whose failure counts, any failure if unset
+    pub failed_branch_jobs: Option<String>,
+    /// What an un-played manual blocking job does, `allow` (ignored) by default
+    pub manual_jobs: Option<Action>,
+    /// Check the pipelines of open merge requests from the pushed branch, on by default
+    pub merge_request_pipelines: Option<bool>,
+    /// Check merge train pipelines into the pushed branch, off by default
+    pub merge_train_pipelines: Option<bool>,
+    /// What to do when the pushed branch's merge request is on a merge train,
+    /// `allow` by default
+    pub merge_train: Option<MergeTrainAction>,
+    /// Policy per pipeline status, overriding the defaults
+    pub pipeline_statuses: Option<BTreeMap<String, StatusPolicy>>,
+    /// Policy per job status, overriding the defaults
+    pub job_statuses: Option<BTreeMap<String, StatusPolicy>>,
+    /// Per-instance settings keyed by `host` or `host/path-prefix`
+    pub profiles: Option<BTreeMap<String, Config>>,
+    /// Key of the profile applied by `load`, for display
+    #[serde(skip)]
+    pub profile: Option<String>,
+}
+
+/// Pipelines to check besides the ones on the pushed refs.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct WatchTarget {
+    /// Project path, the pushed project if unset
+    pub project: Option<String>,
+    /// Ref name pattern, e.g. `main` or `release/*`
+    #[serde(rename = "ref")]
+    pub ref_pattern: String,
+}
+
+/// What a merge train holding the pushed branch's merge request does.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MergeTrainAction {
+    Block,
+    /// Ask on the terminal, block without one
+    Confirm,
+    Allow,
+}
+
+impl fmt::Display for MergeTrainAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            MergeTrainAction::Block => "block",
+            MergeTrainAction::Confirm => "confirm",
+            MergeTrainAction::Allow => "allow",
+        })
+    }
+}
+
+impl Config {
+    /// Fill the fields left unset in `self` from a lower precedence layer.
+    pub fn or(self, lower: Config) -> Config {
+        Config {
+            token: self.token.or(lower.token),
+            gitlab_url: self.gitlab_url.or(lower.gitlab_url),
+            blocking_stage: self.blocking_stage.or(lower.blocking_stage),
+            blocking_jobs: self.blocking_jobs.or(lower.blocking_jobs),
+            blocking_environments: self.blocking_environments.or(lower.blocking_environments),
+            pre_block_duration: self.pre_block_duration.or(lower.pre_block_duration),
+            post_block_duration: self.post_block_duration.or(lower.post_block_duration),
+            check_interval: self.check_interval.or(lower.check_interval),
+            min_check_interval: self.min_check_interval.or(lower.min_check_interval),
+            max_check_interval: self.max_check_interval.or(lower.max_check_interval),
+            max_pages: self.max_pages.or(lower.max_pages),
+            timeout: self.timeout.or(lower.timeout),
+            eta_pipelines: self.eta_pipelines.or(lower.eta_pipelines),
+            simple_mode: self.simple_mode.or(lower.simple_mode),
+            rules: self.rules.or(lower.rules),
+            watch: self.watch.or(lower.watch),
+            freeze_branches: self.freeze_branches.or(lower.freeze_branches),
+            freeze_action: self.freeze_action.or(lower.freeze_action),
+            blackouts: self.blackouts.or(lower.blackouts),
+            failed_branch: self.failed_branch.or(lower.failed_branch),
+            failed_branch_jobs: self.failed_branch_jobs.or(lower.failed_branch_jobs),
+            manual_jobs: self.manual_jobs.or(lower.manual_jobs),
+            merge_request_pipelines: self
+                .merge_request_pipelines
+                .or(lower.merge_request_pipelines),
+            merge_train_pipelines: self.merge_train_pipelines.or(lower.merge_train_pipelines),
+            merge_train: self.merge_train.or(lower.merge_train),
+            pipeline_statuses: self.pipeline_statuses.or(lower.pipeline_statuses),
+            job_statuses: self.job_statuses.or(lower.job_statuses),
+            profiles: match (self.profiles, lower.profiles) {
+                (Some(mut upper), Some(lower)) => {
+                    for (key, profile) in lower {
+                        upper.entry(key).or_insert(profile);
+                    }
+                    Some(upper)
+                }
+                (upper, lower) => upper.or(lower),
+            },
+            profile: self.profile.or(lower.profile),
+        }
+    }
+
+    /// The profile whose key matches the remote host and, if the key has
+    /// one, the path prefix. The prefix is a namespace of the remote path,
+    /// unless it is the path of the profile's `gitlab_url`: SSH remotes of
+    /// an instance served under a sub-path don't carry it, so it then
+    /// matches every remote of the host. The most specific key wins.
+    fn matching_profile(&self, host: &str, path: &str) -> Option<(&String, &Config)> {
+        self.profiles
+            .as_ref()?
+            .iter()
+            .filter(|(key, profile)| {
+                let (key_host, prefix) = key.split_once('/').unwrap_or((key, ""));
+                let prefix = prefix.trim_matches('/');
+                let instance_path = profile
+                    .gitlab_url
+                    .as_deref()
+                    .and_then(|url| Url::parse(url).ok())
+                    .map(|url| url.path().trim_matches('/').to_string());
+                key_host.eq_ignore_ascii_case(host)
+                    && (prefix.is_empty()
+                        || instance_path.as_deref() == Some(prefix)
+                        || path == prefix
+                        || path.starts_with(&format!("{}/", prefix)))
+            })
+            .max_by_key(|(key, _)| key.len())
+    }
+
+    /// Merge the command line layer with every configuration source,
+    /// applying the profile for the remote. `env` holds the token and URL
+    /// from the environment, kept apart from the flags in `cli`.
+    ///
+    /// Missing files are skipped; unreadable or invalid ones are an error so
+    /// a typo never silently disables the blocking rules.
+    pub fn load(cli: Config, env: Config, remote: &RemoteUrl) -> Result<Config, ConfigError> {
+        Ok(Self::merge(
+            cli,
+            env,
+            Self::load_git_config()?,
+            Self::load_repo()?.unwrap_or_default(),
+            Self::load_global()?.unwrap_or_default(),
+            remote,
+        ))
+    }
+
+    /// Layer the sources by precedence. The token and URL of a matching
+    /// profile outrank the environment and git config, which apply to every
+    /// host. A profile without a token still takes theirs.
+    ///
+    /// `rules` and the keys they replace are taken as a whole from the
+    /// highest source setting any of them, so neither silently overrides
+    /// a source that ranks above it.
+    fn merge(
+        mut cli: Config,
+        env: Config,
+        git: Config,
+        repo: Config,
+        global: Config,
+        remote: &RemoteUrl,
+    ) -> Config {
+        let profile = global
+            .matching_profile(&remote.host, &remote.path)
+            .map(|(key, profile)| Config {
+                // The API lives on the remote host unless the profile says otherwise
+                gitlab_url: Some(
+                    profile
+                        .gitlab_url
+                        .clone()
+                        .unwrap_or_else(|| remote.web_url()),
+                ),
+                profiles: None,
+                profile: Some(key.clone()),
+                ..profile.clone()
+            })
+            .unwrap_or_default();
+
+        let flags = Config {
+            token: cli.token.take(),
+            gitlab_url: cli.gitlab_url.take(),
+            ..Config::default()
+        };
+        let profile_credentials = Config {
+            token: profile.token.clone(),
+            gitlab_url: profile.gitlab_url.clone(),
+            ..Config::default()
+        };
+
+        let rules_win = [&env, &cli, &git, &repo, &profile, &global]
+            .into_iter()
+            .find(|layer| layer.rules.is_some() || layer.replaced_key().is_some())
+            .is_some_and(|layer| layer.rules.is_some());
+
+        let mut config = flags
+            .or(profile_credentials)
+            .or(env)
+            .or(cli)
+            .or(git)
+            .or(repo)
+            .or(profile)
+            .or(global);
+        if rules_win {
+            config.blocking_stage = None;
+            config.blocking_jobs = None;
+            config.manual_jobs = None;
+        } else {
+            config.rules = None;
+        }
+        config
+    }
+
+    /// The first key `rules` replace, if set alongside them.
+    fn replaced_by_rules(&self) -> Option<&'static str> {
+        self.rules.as_ref()?;
+        self.replaced_key()
+    }
+
+    /// The first key set that `rules` would replace.
+    fn replaced_key(&self) -> Option<&'static str> {
+        [
+            ("blocking_stage", self.blocking_stage.is_some()),
+            ("blocking_jobs", self.blocking_jobs.is_some()),
+            ("manual_jobs", self.manual_jobs.is_some()),
+        ]
+        .into_iter()
+        .find(|(_, set)| *set)
+        .map(|(key, _)| key)
+    }
+
+    /// Refuse a file setting `rules` and a key they replace: one of the two
+    /// would be silently ignored.
+    fn check_rules(self, config_path: &Path) -> Result<Config, ConfigError> {
+        let layers = std::iter::once((None, &self)).chain(
+            self.profiles
+                .iter()
+                .flatten()
+                .map(|(key, profile)| (Some(key), profile)),
+        );
+        for (profile, layer) in layers {
+            if let Some(key) = layer.replaced_by_rules() {
+                let profile = profile
+                    .map(|profile| format!("profile `{}`: ", profile))
+                    .unwrap_or_default();
+                return Err(ConfigError::Conflict {
+                    path: config_path.to_path_buf(),
+                    message: format!(
+                        "{}rules replace `{}`: express it as a rule or remove it",
+                        profile, key
+                    ),
+                });
+            }
+        }
+        Ok(self)
+    }
+
+    /// `~/.gitlab-safe-push-config.json`
+    fn load_global() -> Result<Option<Config>, ConfigError> {
+        let Some(home) = dirs::home_dir() else {
+            return Ok(None);
+        };
+        let config_path = home.join(".gitlab-safe-push-config.json");
+
+        let Some(content) = read_optional(&config_path)? else {
+            return Ok(None);
+        };
+        Self::parse_global(config_path, &content).map(Some)
+    }
+
+    fn parse_global(config_path: PathBuf, content: &str) -> Result<Config, ConfigError> {
+        let config: Config = serde_json::from_str(content)
+            .map_err(|e| parse_error(config_path.clone(), e.line(), e.column(), &e.to_string()))?;
+        config.check_rules(&config_path)
+    }
+
+    /// `.gitlab-safe-push.toml` at the root of the current repository
+    fn load_repo() -> Result<Option<Config>, ConfigError> {
+        let Ok(toplevel) = git::run(&["rev-parse", "--show-toplevel"]) else {
+            return Ok(None);
+        };
+        let config_path = Path::new(&toplevel).join(".gitlab-safe-push.toml");
+
+        let Some(content) = read_optional(&config_path)? else {
+            return Ok(None);
+        };
+        Self::parse_repo(config_path, &content).map(Some)
+    }
+
+    /// Parse a repository file. Credentials and endpoints are refused there:
+    /// a cloned repository could otherwise send the user's token anywhere.
+    fn parse_repo(config_path: PathBuf, content: &str) -> Result<Config, ConfigError> {
+        let to_error = |e: toml::de::Error| {
+            let (line, column) = e
+                .span()
+                .map(|span| line_column(content, span.start))
+                .unwrap_or((1, 1));
+            parse_error(config_path.clone(), line, column, e.message())
+        };
+
+        let keys: BTreeMap<toml::Spanned<String>, IgnoredAny> =
+            toml::from_str(content).map_err(to_error)?;
+        if let Some(key) = keys
+            .keys()
+            .find(|key| REPO_FORBIDDEN_KEYS.contains(&key.as_ref().as_str()))
+        {
+            let (line, column) = line_column(content, key.span().start);
+            return Err(ConfigError::Parse {
+                path: config_path,
+                line,
+                column,
+                message: format!(
+                    "`{}` can't be set in the repository file, set it in the home config, git config or the environment",
+                    key.as_ref()
+                ),
+                suggestion: None,
+            });
+        }
+
+        let config: Config = toml::from_str(content).map_err(to_error)?;
+        config.check_rules(&config_path)
+    }
+
+    /// `safe-push.*` keys from git config, e.g. `safe-push.blockingStage`
+    fn load_git_config() -> Result<Config, ConfigError> {
+        match git::run(&["config", "--get-regexp", r"^safe-push\."]) {
+            Ok(entries) => Self::parse_git_config(&entries),
+            Err(_) => Ok(Config::default()),
+        }
+    }
+
+    /// Parse `git config --get-regexp` output, one `key value` per line.
+    fn parse_git_config(entries: &str) -> Result<Config, ConfigError> {
+        let mut config = Config::default();
+        for entry in entries.lines() {
+            // A bare `key` line is a boolean set to true
+            let (key, value) = entry.split_once(' ').unwrap_or((entry, "true"));
+            let value = value.trim().to_string();
+            let invalid = |expected: &str| ConfigError::GitConfig {
+                key: key.to_string(),
+                message: format!("invalid value '{}', expected {}", value, expected),
+                suggestion: None,
+            };
+
+            // git lowercases variable names in --get-regexp output
+            match key.trim_start_matches("safe-push.") {
+                "token" => config.token = Some(value),
+                "gitlaburl" => config.gitlab_url = Some(value),
+                "blockingstage" => config.blocking_stage = Some(value),
+                "blockingjobs" => config.blocking_jobs = Some(value),
+                "blockingenvironments" => config.blocking_environments = Some(value),
+                "preblockduration" => {
+                    config.pre_block_duration =
+                        Some(value.parse().map_err(|_| invalid("a number of seconds"))?)
+                }
+                "postblockduration" => {
+                    config.post_block_duration =
+                        Some(value.parse().map_err(|_| invalid("a number of seconds"))?)
+                }
+                "checkinterval" => {
+                    config.check_interval =
+                        Some(value.parse().map_err(|_| invalid("a number of seconds"))?)
+                }
+                "mincheckinterval" => {
+                    config.min_check_interval =
+                        Some(value.parse().map_err(|_| invalid("a number of seconds"))?)
+                }
+                "maxcheckinterval" => {
+                    config.max_check_interval =
+                        Some(value.parse().map_err(|_| invalid("a number of seconds"))?)
+                }
+                "maxpages" => {
+                    config.max_pages = Some(value.parse().map_err(|_| invalid("a number"))?)
+                }
+                "etapipelines" => {
+                    config.eta_pipelines = Some(value.parse().map_err(|_| invalid("a number"))?)
+                }
+                "timeout" => {
+                    humantime::parse_duration(&value)
+                        .map_err(|_| invalid("a duration such as 15m"))?;
+                    config.timeout = Some(value)
+                }
+                "freezebranches" => config.freeze_branches = Some(value),
+                "freezeaction" => {
+                    config.freeze_action =
+                        Some(parse_action(&value).ok_or(invalid("block, warn or allow"))?)
+                }
+                "failedbranch" => {
+                    config.failed_branch =
+                        Some(parse_action(&value).ok_or(invalid("block, warn or allow"))?)
+                }
+                "failedbranchjobs" => config.failed_branch_jobs = Some(value),
+                "manualjobs" => {
+                    config.manual_jobs =
+                        Some(parse_action(&value).ok_or(invalid("block, warn or allow"))?)
+                }
+                "mergerequestpipelines" => {
+                    config.merge_request_pipelines =
+                        Some(parse_git_bool(&value).ok_or(invalid("a boolean"))?)
+                }
+                "mergetrainpipelines" => {
+                    config.merge_train_pipelines =
+                        Some(parse_git_bool(&value).ok_or(invalid("a boolean"))?)
+                }
+                "mergetrain" => {
+                    config.merge_train = Some(match value.to_ascii_lowercase().as_str() {
+                        "block" => MergeTrainAction::Block,
+                        "confirm" => MergeTrainAction::Confirm,
+                        "allow" => MergeTrainAction::Allow,
+                        _ => return Err(invalid("block, confirm or allow")),
+                    })
+                }
+                "simplemode" => {
+                    config.simple_mode = Some(parse_git_bool(&value).ok_or(invalid("a boolean"))?)
+                }
+                name => {
+                    return Err(ConfigError::GitConfig {
+                        key: key.to_string(),
+                        message: "unknown key".to_string(),
+                        suggestion: closest_match(name, GIT_CONFIG_KEYS.iter().copied()),
+                    })
+                }
+            }
+        }
+
+        Ok(config)
+    }
+}
+
+/// Read a file, `None` if it does not exist.
+fn read_optional(path: &Path) -> Result<Option<String>, ConfigError> {
+    match fs::read_to_string(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(ConfigError::Io {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+/// Build a parse error, suggesting the closest key for serde's
+/// "unknown field `x`, expected one of `a`, `b`" messages.
+fn parse_error(path: PathBuf, line: usize, column: usize, message: &str) -> ConfigError {
+    let message = message
+        .split(" at line ")
+        .next()
+        .unwrap_or(message)
+        .trim()
+        .to_string();
+
+    let mut suggestion = None;
+    if message.starts_with("unknown field") {
+        let re = Regex::new(r"`([^`]+)`").expect("valid regex");
+        let mut names = re.captures_iter(&message).map(|caps| caps[1].to_string());
+        if let Some(unknown) = names.next() {
+            let expected: Vec<String> = names.collect();
+            suggestion = closest_match(&unknown, expected.iter().map(|s| s.as_str()));
+        }
+    }
+
+    ConfigError::Parse {
+        path,
+        line,
+        column,
+        message,
+        suggestion,
+    }
+}
+
+/// 1-based line and column of a byte offset.
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.len() - before.rfind('\n').map(|i| i + 1).unwrap_or(0) + 1;
+    (line, column)
+}
+
+/// The candidate closest to `name`, if it is close enough to be a typo.
+fn closest_match<'a>(name: &str, candidates: impl Iterator<Item = &'a str>) -> Option<String> {
+    let name = name.to_ascii_lowercase();
+    candidates
+        .map(|candidate| {
+            let distance = strsim::levenshtein(&name, &candidate.to_ascii_lowercase());
+            (distance, candidate)
+        })
+        .filter(|(distance, _)| *distance <= 3.max(name.len() / 3))
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+fn parse_action(value: &str) -> Option<Action> {
+    match value {
+        "block" => Some(Action::Block),
+        "warn" => Some(Action::Warn),
+        "allow" => Some(Action::Allow),
+        _ => None,
+    }
+}
+
+/// Git's boolean spellings
+fn parse_git_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_position_and_suggestion_of_file_errors() {
+        let json =
+            "{\n  \"gitlab_url\": \"https://gitlab.com\",\n  \"blocking_stgae\": \"deploy\"\n}";
+        match Config::parse_global(PathBuf::from("config.json"), json) {
+            Err(ConfigError::Parse {
+                line,
+                message,
+                suggestion,
+                ..
+            }) => {
+                assert_eq!(line, 3);
+                assert!(
+                    message.starts_with("unknown field `blocking_stgae`"),
+                    "{}",
+                    message
+                );
+                assert!(!message.contains(" at line "), "{}", message);
+                assert_eq!(suggestion.as_deref(), Some("blocking_stage"));
+            }
+            _ => panic!("unknown key accepted"),
+        }
+
+        let toml = "blocking_stage = \"deploy\"\ncheck_interval = \"30\"\n";
+        match Config::parse_repo(PathBuf::from(".gitlab-safe-push.toml"), toml) {
+            Err(e @ ConfigError::Parse { .. }) => {
+                assert!(
+                    e.to_string()
+                        .starts_with(".gitlab-safe-push.toml:2:18: invalid type"),
+                    "{}",
+                    e
+                );
+            }
+            _ => panic!("string accepted as a number"),
+        }
+    }
+
+    #[test]
+    fn reports_git_config_errors() {
+        let config =
+            Config::parse_git_config("safe-push.blockingstage deploy\nsafe-push.simplemode")
+                .unwrap();
+        assert_eq!(config.blocking_stage.as_deref(), Some("deploy"));
+        assert_eq!(config.simple_mode, Some(true));
+
+        match Config::parse_git_config("safe-push.blockingstgae deploy") {
+            Err(ConfigError::GitConfig {
+                key, suggestion, ..
+            }) => {
+                assert_eq!(key, "safe-push.blockingstgae");
+                assert_eq!(suggestion.as_deref(), Some("blockingStage"));
+            }
+            _ => panic!("unknown key accepted"),
+        }
+
+        match Config::parse_git_config("safe-push.checkinterval soon") {
+            Err(e) => assert_eq!(
+                e.to_string(),
+                "git config safe-push.checkinterval: invalid value 'soon', expected a number of seconds"
+            ),
+            Ok(_) => panic!("bad value accepted"),
+        }
+    }
+
+    #[test]
+    fn finds_line_and_column_of_offsets() {
+        let content = "a = 1\nbb = 2\n";
+        assert_eq!(line_column(content, 0), (1, 1));
+        assert_eq!(line_column(content, 4), (1, 5));
+        assert_eq!(line_column(content, 6), (2, 1));
+        assert_eq!(line_column(content, 11), (2, 6));
+        assert_eq!(line_column(content, 100), (3, 1));
+    }
+
+    fn profiles(keys: &[&str]) -> Config {
+        let profiles = keys
+            .iter()
+            .map(|key| {
+                let profile = Config {
+                    token: Some(format!("token of {}", key)),
+                    ..Config::default()
+                };
+                (key.to_string(), profile)
+            })
+            .collect();
+        Config {
+            profiles: Some(profiles),
+            ..Config::default()
+        }
+    }
+
+    #[test]
+    fn picks_the_most_specific_profile() {
+        let config = profiles(&[
+            "gitlab.com",
+            "git.example.com",
+            "git.example.com/team",
+            "git.example.com/team/sub",
+            "git.example.com/prefix/",
+        ]);
+
+        let cases = [
+            ("gitlab.com", "group/proj", Some("gitlab.com")),
+            ("GitLab.com", "group/proj", Some("gitlab.com")),
+            (
+                "git.example.com",
+                "team/sub/proj",
+                Some("git.example.com/team/sub"),
+            ),
+            ("git.example.com", "team/proj", Some("git.example.com/team")),
+            (
+                "git.example.com",
+                "prefix/proj",
+                Some("git.example.com/prefix/"),
+            ),
+            // Prefixes match whole path segments
+            ("git.example.com", "prefixer/proj", Some("git.example.com")),
+            ("git.example.com", "teams/proj", Some("git.example.com")),
+            ("other.example.com", "team/proj", None),
+        ];
+
+        for (host, path, expected) in cases {
+            let key = config
+                .matching_profile(host, path)
+                .map(|(key, _)| key.as_str());
+            assert_eq!(key, expected, "{}/{}", host, path);
+        }
+    }
+
+    #[test]
+    fn matches_sub_path_instances_without_the_prefix() {
+        let mut config = profiles(&["git.example.com", "git.example.com/gitlab"]);
+        let profiles = config.profiles.as_mut().unwrap();
+        profiles
+            .get_mut("git.example.com/gitlab")
+            .unwrap()
+            .gitlab_url = Some("https://git.example.com/gitlab/".to_string());
+
+        let cases = [
+            // SSH remotes
+            ("group/proj", "git.example.com/gitlab"),
+            // HTTP remotes
+            ("gitlab/group/proj", "git.example.com/gitlab"),
+        ];
+        for (path, expected) in cases {
+            let key = config
+                .matching_profile("git.example.com", path)
+                .map(|(key, _)| key.as_str());
+            assert_eq!(key, Some(expected), "{}", path);
+        }
+    }
+
+    #[test]
+    fn profile_credentials_outrank_environment() {
+        let remote = RemoteUrl::parse("http://git.example.com:8080/team/proj.git").unwrap();
+        let env = Config {
+            token: Some("env token".to_string()),
+            gitlab_url: Some("https://gitlab.com".to_string()),
+            ..Config::default()
+        };
+        let git = Config {
+            token: Some("git config token".to_string()),
+            blocking_stage: Some("deploy".to_string()),
+            ..Config::default()
+        };
+        let global = || Config {
+            token: Some("default token".to_string()),
+            ..profiles(&["git.example.com"])
+        };
+
+        let config = Config::merge(
+            Config::default(),
+            env.clone(),
+            git.clone(),
+            Config::default(),
+            global(),
+            &remote,
+        );
+        assert_eq!(config.token.as_deref(), Some("token of git.example.com"));
+        assert_eq!(
+            config.gitlab_url.as_deref(),
+            Some("http://git.example.com:8080")
+        );
+        assert_eq!(config.blocking_stage.as_deref(), Some("deploy"));
+        assert_eq!(config.profile.as_deref(), Some("git.example.com"));
+
+        // Only flags override the profile
+        let flags = Config {
+            token: Some("flag token".to_string()),
+            ..Config::default()
+        };
+        let config = Config::merge(
+            flags,
+            env.clone(),
+            git,
+            Config::default(),
+            global(),
+            &remote,
+        );
+        assert_eq!(config.token.as_deref(), Some("flag token"));
+
+        // A profile without a token takes the environment's to its instance
+        let tokenless = Config {
+            profiles: Some(BTreeMap::from([(
+                "git.example.com".to_string(),
+                Config {
+                    blocking_stage: Some("build".to_string()),
+                    ..Config::default()
+                },
+            )])),
+            ..Config::default()
+        };
+        let config = Config::merge(
+            Config::default(),
+            env.clone(),
+            Config::default(),
+            Config::default(),
+            tokenless,
+            &remote,
+        );
+        assert_eq!(config.token.as_deref(), Some("env token"));
+        assert_eq!(
+            config.gitlab_url.as_deref(),
+            Some("http://git.example.com:8080")
+        );
+
+        // Without a matching profile, the environment applies
+        let other = RemoteUrl::parse("git@gitlab.com:group/proj.git").unwrap();
+        let config = Config::merge(
+            Config::default(),
+            env,
+            Config::default(),
+            Config::default(),
+            global(),
+            &other,
+        );
+        assert_eq!(config.token.as_deref(), Some("env token"));
+        assert_eq!(config.gitlab_url.as_deref(), Some("https://gitlab.com"));
+    }
+
+    #[test]
+    fn rules_and_replaced_keys_follow_precedence() {
+        let path = PathBuf::from(".gitlab-safe-push.toml");
+        let rules = "[[rules]]\naction = \"block\"\nstage = \"deploy\"\n";
+        let remote = RemoteUrl::parse("git@gitlab.com:group/proj.git").unwrap();
+        let home_rules = || {
+            Config::parse_global(
+                PathBuf::from("config.json"),
+                r#"{"rules": [{"action": "block"}]}"#,
+            )
+            .unwrap()
+        };
+        let merge = |cli: Config, git: Config, repo: Config, global: Config| {
+            Config::merge(cli, Config::default(), git, repo, global, &remote)
+        };
+
+        // Repository rules replace a home default
+        let home = Config {
+            blocking_stage: Some("deploy".to_string()),
+            manual_jobs: Some(Action::Block),
+            ..Config::default()
+        };
+        let config = merge(
+            Config::default(),
+            Config::default(),
+            Config::parse_repo(path.clone(), rules).unwrap(),
+            home,
+        );
+        assert_eq!(config.rules.map(|rules| rules.len()), Some(1));
+        assert!(config.blocking_stage.is_none() && config.manual_jobs.is_none());
+
+        // A repository blocking stage replaces home rules
+        let repo = Config::parse_repo(path.clone(), "blocking_stage = \"deploy\"\n").unwrap();
+        let config = merge(Config::default(), Config::default(), repo, home_rules());
+        assert!(config.rules.is_none());
+        assert_eq!(config.blocking_stage.as_deref(), Some("deploy"));
+
+        // So do git config and flags, over repository rules too
+        let stage = |stage: &str| Config {
+            blocking_stage: Some(stage.to_string()),
+            ..Config::default()
+        };
+        let config = merge(
+            Config::default(),
+            stage("build"),
+            Config::default(),
+            home_rules(),
+        );
+        assert!(config.rules.is_none());
+        assert_eq!(config.blocking_stage.as_deref(), Some("build"));
+        let config = merge(
+            stage("release"),
+            Config::default(),
+            Config::parse_repo(path.clone(), rules).unwrap(),
+            Config::default(),
+        );
+        assert!(config.rules.is_none());
+        assert_eq!(config.blocking_stage.as_deref(), Some("release"));
+
+        // Within one file, one of the two would be ignored
+        let content = format!("blocking_stage = \"deploy\"\n{}", rules);
+        match Config::parse_repo(path, &content) {
+            Err(ConfigError::Conflict { message, .. }) => {
+                assert!(message.contains("`blocking_stage`"), "{}", message)
+            }
+            _ => panic!("rules accepted alongside blocking_stage"),
+        }
+        let content = r#"{"profiles": {"gitlab.com": {"blocking_jobs": "deploy", "rules": [{"action": "block"}]}}}"#;
+        match Config::parse_global(PathBuf::from("config.json"), content) {
+            Err(ConfigError::Conflict { message, .. }) => {
+                assert!(message.starts_with("profile `gitlab.com`"), "{}", message)
+            }
+            _ => panic!("rules accepted alongside blocking_jobs"),
+        }
+    }
+
+    #[test]
+    fn repository_file_cant_redirect_credentials() {
+        let path = PathBuf::from(".gitlab-safe-push.toml");
+        let config = Config::parse_repo(path.clone(), "blocking_stage = \"deploy\"\n").unwrap();
+        assert_eq!(config.blocking_stage.as_deref(), Some("deploy"));
+
+        let cases = [
+            (
+                "blocking_stage = \"deploy\"\ntoken = \"glpat-x\"\n",
+                2,
+                "token",
+            ),
+            ("gitlab_url = \"https://attacker\"\n", 1, "gitlab_url"),
+            (
+                "[profiles.\"gitlab.com\"]\ntoken = \"glpat-x\"\n",
+                1,
+                "profiles",
+            ),
+        ];
+        for (content, expected_line, key) in cases {
+            match Config::parse_repo(path.clone(), content) {
+                Err(ConfigError::Parse { line, message, .. }) => {
+                    assert_eq!(line, expected_line, "{}", content);
+                    assert!(message.starts_with(&format!("`{}`", key)), "{}", message);
+                }
+                _ => panic!("{} accepted", key),
+            }
+        }
+    }
+}
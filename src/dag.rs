@@ -0,0 +1,252 @@
+use serde_json::Value;
+use std::collections::HashSet;
+
+/// Stages and job dependencies of a pipeline, as GitLab schedules them.
+#[derive(Clone)]
+pub struct PipelineGraph {
+    pub stages: Vec<String>,
+    jobs: Vec<GraphJob>,
+}
+
+#[derive(Clone)]
+struct GraphJob {
+    name: String,
+    stage: String,
+    /// Scheduled by `needs:` rather than by stage
+    dag: bool,
+    needs: Vec<String>,
+}
+
+impl PipelineGraph {
+    /// Build from the GraphQL `pipeline { stages { nodes { name groups {
+    /// nodes { jobs { nodes { name schedulingType needs { nodes { name } } } } } } } } }`.
+    pub fn from_graphql(pipeline: &Value) -> Option<Self> {
+        let mut stages = Vec::new();
+        let mut jobs = Vec::new();
+
+        for stage in pipeline.pointer("/stages/nodes")?.as_array()? {
+            let stage_name = stage["name"].as_str()?.to_string();
+
+            let groups = stage
+                .pointer("/groups/nodes")
+                .and_then(|groups| groups.as_array())
+                .into_iter()
+                .flatten();
+            for group in groups {
+                let group_jobs = group
+                    .pointer("/jobs/nodes")
+                    .and_then(|jobs| jobs.as_array())
+                    .into_iter()
+                    .flatten();
+                for job in group_jobs {
+                    let Some(name) = job["name"].as_str() else {
+                        continue;
+                    };
+                    let needs = job
+                        .pointer("/needs/nodes")
+                        .and_then(|needs| needs.as_array())
+                        .into_iter()
+                        .flatten()
+                        .filter_map(|need| need["name"].as_str().map(|n| n.to_string()))
+                        .collect();
+
+                    jobs.push(GraphJob {
+                        name: name.to_string(),
+                        stage: stage_name.clone(),
+                        dag: job["schedulingType"].as_str() == Some("dag"),
+                        needs,
+                    });
+                }
+            }
+
+            stages.push(stage_name);
+        }
+
+        Some(Self { stages, jobs })
+    }
+
+    /// Jobs `name` directly waits for: its `needs:` for DAG jobs, otherwise
+    /// every job of the previous stage.
+    pub fn prerequisites(&self, name: &str) -> Vec<&str> {
+        let Some(job) = self.jobs.iter().find(|job| job.name == name) else {
+            return Vec::new();
+        };
+
+        if job.dag {
+            return self
+                .jobs
+                .iter()
+                .filter(|other| job.needs.iter().any(|need| need_matches(need, &other.name)))
+                .map(|other| other.name.as_str())
+                .collect();
+        }
+
+        let Some(stage_idx) = self.stages.iter().position(|stage| *stage == job.stage) else {
+            return Vec::new();
+        };
+        let Some(previous_stage) = stage_idx.checked_sub(1).map(|idx| &self.stages[idx]) else {
+            return Vec::new();
+        };
+
+        self.jobs
+            .iter()
+            .filter(|other| other.stage == *previous_stage)
+            .map(|other| other.name.as_str())
+            .collect()
+    }
+
+    /// Every job `name` waits for, directly or through the `needs:` of
+    /// other jobs. A job scheduled by stage only adds the previous stage:
+    /// the stages before it are done by the time it runs.
+    pub fn transitive_prerequisites(&self, name: &str) -> HashSet<&str> {
+        let mut seen = HashSet::new();
+        let mut queue = self.prerequisites(name);
+
+        while let Some(job) = queue.pop() {
+            if seen.insert(job) && self.is_dag(job) {
+                queue.extend(self.prerequisites(job));
+            }
+        }
+
+        seen
+    }
+
+    fn is_dag(&self, name: &str) -> bool {
+        self.jobs.iter().any(|job| job.name == name && job.dag)
+    }
+}
+
+/// Whether a `needs:` entry refers to a job. Entries naming a parallel or
+/// matrix job refer to all its instances (`deploy 1/3`, `deploy: [eu]`).
+fn need_matches(need: &str, job_name: &str) -> bool {
+    let Some(rest) = job_name.strip_prefix(need) else {
+        return false;
+    };
+    let is_number = |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_digit());
+
+    rest.is_empty()
+        || rest
+            .strip_prefix(' ')
+            .and_then(|index| index.split_once('/'))
+            .is_some_and(|(index, total)| is_number(index) && is_number(total))
+        || (rest.starts_with(": [") && rest.ends_with(']'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn job(name: &str, dag: bool, needs: &[&str]) -> Value {
+        let needs: Vec<Value> = needs.iter().map(|need| json!({ "name": need })).collect();
+        json!({
+            "name": name,
+            "schedulingType": if dag { "dag" } else { "stage" },
+            "needs": { "nodes": needs },
+        })
+    }
+
+    fn stage(name: &str, jobs: Vec<Value>) -> Value {
+        json!({ "name": name, "groups": { "nodes": [{ "jobs": { "nodes": jobs } }] } })
+    }
+
+    fn graph() -> PipelineGraph {
+        let pipeline = json!({ "stages": { "nodes": [
+            stage("build", vec![job("compile", false, &[]), job("lint", false, &[])]),
+            stage("test", vec![
+                job("unit", false, &[]),
+                job("deploy prod", false, &[]),
+                job("e2e 1/2", true, &["compile"]),
+                job("e2e 2/2", true, &["compile"]),
+            ]),
+            stage("deploy", vec![
+                job("deploy: [eu]", true, &["e2e"]),
+                job("deploy", true, &["unit"]),
+                job("notify", false, &[]),
+            ]),
+        ] } });
+        PipelineGraph::from_graphql(&pipeline).unwrap()
+    }
+
+    fn sorted<'a>(names: impl IntoIterator<Item = &'a str>) -> Vec<&'a str> {
+        let mut names: Vec<&str> = names.into_iter().collect();
+        names.sort();
+        names
+    }
+
+    #[test]
+    fn reads_stages_and_jobs_from_graphql() {
+        let graph = graph();
+        assert_eq!(graph.stages, ["build", "test", "deploy"]);
+        assert_eq!(graph.jobs.len(), 9);
+        assert!(PipelineGraph::from_graphql(&json!({})).is_none());
+    }
+
+    #[test]
+    fn finds_direct_prerequisites() {
+        let graph = graph();
+        let cases: [(&str, &[&str]); 6] = [
+            // Stage jobs wait for the whole previous stage
+            ("unit", &["compile", "lint"]),
+            ("notify", &["deploy prod", "e2e 1/2", "e2e 2/2", "unit"]),
+            ("compile", &[]),
+            // DAG jobs only for their needs, parallel instances included
+            ("e2e 1/2", &["compile"]),
+            ("deploy: [eu]", &["e2e 1/2", "e2e 2/2"]),
+            ("deploy", &["unit"]),
+        ];
+
+        for (job, expected) in cases {
+            assert_eq!(sorted(graph.prerequisites(job)), expected, "{}", job);
+        }
+        assert!(graph.prerequisites("missing").is_empty());
+    }
+
+    #[test]
+    fn follows_prerequisites_transitively() {
+        let graph = graph();
+        assert_eq!(
+            sorted(graph.transitive_prerequisites("deploy: [eu]")),
+            ["compile", "e2e 1/2", "e2e 2/2"]
+        );
+        // Through the needs of the previous stage, not the stages before
+        assert_eq!(
+            sorted(graph.transitive_prerequisites("notify")),
+            ["compile", "deploy prod", "e2e 1/2", "e2e 2/2", "unit"]
+        );
+    }
+
+    #[test]
+    fn stage_jobs_wait_for_the_previous_stage_only() {
+        let pipeline = json!({ "stages": { "nodes": [
+            stage("build", vec![job("compile", false, &[])]),
+            stage("test", vec![job("unit", false, &[]), job("lint", false, &[])]),
+            stage("deploy", vec![job("deploy", false, &[])]),
+        ] } });
+        let graph = PipelineGraph::from_graphql(&pipeline).unwrap();
+
+        assert_eq!(
+            sorted(graph.transitive_prerequisites("deploy")),
+            ["lint", "unit"]
+        );
+    }
+
+    #[test]
+    fn matches_needs_to_job_instances() {
+        let cases = [
+            ("deploy", "deploy", true),
+            ("deploy", "deploy 1/3", true),
+            ("deploy", "deploy 12/12", true),
+            ("deploy", "deploy: [eu, prod]", true),
+            ("deploy", "deploy prod", false),
+            ("deploy", "deploy 1/", false),
+            ("deploy", "deployment", false),
+            ("deploy", "deploy: eu", false),
+            ("deploy prod", "deploy prod", true),
+        ];
+
+        for (need, job, expected) in cases {
+            assert_eq!(need_matches(need, job), expected, "{} ~ {}", need, job);
+        }
+    }
+}
@@ -0,0 +1,63 @@
+use std::collections::HashMap;
+
+/// How long jobs took in recent successful pipelines, by job name.
+#[derive(Default, Clone, Debug)]
+pub struct JobHistory {
+    durations: HashMap<String, Vec<f64>>,
+}
+
+impl JobHistory {
+    pub fn record(&mut self, job: &str, seconds: f64) {
+        self.durations
+            .entry(job.to_string())
+            .or_default()
+            .push(seconds);
+    }
+
+    /// Median duration of a job, in seconds. The median keeps a single
+    /// retried or stuck run from skewing the estimate.
+    pub fn typical(&self, job: &str) -> Option<f64> {
+        let mut durations = self.durations.get(job)?.clone();
+        if durations.is_empty() {
+            return None;
+        }
+        durations.sort_by(f64::total_cmp);
+
+        let middle = durations.len() / 2;
+        Some(if durations.len() % 2 == 0 {
+            (durations[middle - 1] + durations[middle]) / 2.0
+        } else {
+            durations[middle]
+        })
+    }
+
+    /// Seconds left for a job that has been running for `elapsed` seconds,
+    /// zero once it runs longer than usual.
+    pub fn remaining(&self, job: &str, elapsed: u64) -> Option<u64> {
+        let typical = self.typical(job)?.round() as u64;
+        Some(typical.saturating_sub(elapsed))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn estimates_from_the_median_run() {
+        let mut history = JobHistory::default();
+        for seconds in [120.0, 600.0, 100.0] {
+            history.record("deploy", seconds);
+        }
+        history.record("plan", 30.0);
+        history.record("plan", 40.0);
+
+        assert_eq!(history.typical("deploy"), Some(120.0));
+        assert_eq!(history.typical("plan"), Some(35.0));
+        assert_eq!(history.typical("lint"), None);
+
+        assert_eq!(history.remaining("deploy", 20), Some(100));
+        assert_eq!(history.remaining("deploy", 500), Some(0));
+        assert_eq!(history.remaining("lint", 0), None);
+    }
+}
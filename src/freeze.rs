@@ -0,0 +1,116 @@
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use croner::Cron;
+use serde::Deserialize;
+
+/// A deploy freeze period as returned by `/projects/:id/freeze_periods`.
+#[derive(Deserialize, Debug)]
+pub struct FreezePeriod {
+    pub freeze_start: String,
+    pub freeze_end: String,
+    pub cron_timezone: String,
+}
+
+impl FreezePeriod {
+    /// When the freeze ends, if it is in effect at `now`.
+    ///
+    /// Like GitLab, a freeze is in effect when its start cron fired more
+    /// recently than its end cron.
+    pub fn active_until(&self, now: DateTime<Utc>) -> Result<Option<DateTime<Utc>>, String> {
+        let tz: Tz = self
+            .cron_timezone
+            .parse()
+            .map_err(|_| format!("unknown timezone '{}'", self.cron_timezone))?;
+        let start = parse_cron(&self.freeze_start)?;
+        let end = parse_cron(&self.freeze_end)?;
+        let now = now.with_timezone(&tz);
+
+        let Ok(last_start) = start.find_previous_occurrence(&now, true) else {
+            return Ok(None);
+        };
+        if let Ok(last_end) = end.find_previous_occurrence(&now, true) {
+            if last_end >= last_start {
+                return Ok(None);
+            }
+        }
+
+        let next_end = end
+            .find_next_occurrence(&now, false)
+            .map_err(|e| format!("freeze end '{}': {}", self.freeze_end, e))?;
+        Ok(Some(next_end.with_timezone(&Utc)))
+    }
+}
+
+fn parse_cron(pattern: &str) -> Result<Cron, String> {
+    pattern
+        .parse()
+        .map_err(|e| format!("invalid cron '{}': {}", pattern, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn period(start: &str, end: &str, tz: &str) -> FreezePeriod {
+        FreezePeriod {
+            freeze_start: start.to_string(),
+            freeze_end: end.to_string(),
+            cron_timezone: tz.to_string(),
+        }
+    }
+
+    fn at(datetime: &str) -> DateTime<Utc> {
+        datetime.parse().unwrap()
+    }
+
+    #[test]
+    fn weekend_freeze() {
+        // Friday 23:00 to Monday 07:00
+        let weekend = period("0 23 * * 5", "0 7 * * 1", "UTC");
+
+        let cases = [
+            // Friday 2026-10-16
+            ("2026-10-16T22:59:00Z", None),
+            ("2026-10-16T23:00:00Z", Some("2026-10-19T07:00:00Z")),
+            ("2026-10-18T12:00:00Z", Some("2026-10-19T07:00:00Z")),
+            ("2026-10-19T06:59:59Z", Some("2026-10-19T07:00:00Z")),
+            ("2026-10-19T07:00:00Z", None),
+            ("2026-10-21T12:00:00Z", None),
+        ];
+
+        for (now, expected) in cases {
+            assert_eq!(
+                weekend.active_until(at(now)).unwrap(),
+                expected.map(at),
+                "{}",
+                now
+            );
+        }
+    }
+
+    #[test]
+    fn uses_the_period_timezone() {
+        // Nightly 22:00 to 06:00 in Paris, UTC+2 in October
+        let nightly = period("0 22 * * *", "0 6 * * *", "Europe/Paris");
+
+        assert_eq!(
+            nightly.active_until(at("2026-10-14T19:59:00Z")).unwrap(),
+            None
+        );
+        assert_eq!(
+            nightly.active_until(at("2026-10-14T20:00:00Z")).unwrap(),
+            Some(at("2026-10-15T04:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn rejects_bad_periods() {
+        let now = at("2026-10-16T12:00:00Z");
+        assert!(period("0 23 * * 5", "0 7 * * 1", "Mars/Olympus")
+            .active_until(now)
+            .is_err());
+        assert!(period("not cron", "0 7 * * 1", "UTC")
+            .active_until(now)
+            .is_err());
+    }
+}
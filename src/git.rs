@@ -0,0 +1,475 @@
+use std::process::Command;
+
+use crate::remote::rewrite_url;
+
+/// Run a git command and return its trimmed stdout.
+pub fn run(args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
+    let output = Command::new("git").args(args).output()?;
+
+    if !output.status.success() {
+        return Err(format!(
+            "Git command failed: {}",
+            String::from_utf8_lossy(&output.stderr)
+        )
+        .into());
+    }
+
+    Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
+}
+
+/// Read a single git config value, `None` if the key is unset.
+pub fn config_get(key: &str) -> Option<String> {
+    run(&["config", "--get", key])
+        .ok()
+        .filter(|value| !value.is_empty())
+}
+
+/// Read every value of a multi-valued git config key.
+pub fn config_get_all(key: &str) -> Vec<String> {
+    run(&["config", "--get-all", key])
+        .map(|values| values.lines().map(|line| line.to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// Current branch name, or `None` on a detached HEAD.
+pub fn current_branch() -> Result<Option<String>, Box<dyn std::error::Error>> {
+    let branch = run(&["rev-parse", "--abbrev-ref", "HEAD"])?;
+    Ok(if branch == "HEAD" { None } else { Some(branch) })
+}
+
+/// The parts of a `git push` argument list that decide where the push goes.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PushArgs {
+    pub repository: Option<String>,
+    pub refspecs: Vec<String>,
+    pub delete: bool,
+    /// `--all`, `--mirror`, `--tags` or `--follow-tags`: refs only git can enumerate
+    pub bulk: bool,
+}
+
+impl PushArgs {
+    /// Split git push arguments into options, repository and refspecs.
+    pub fn parse(args: &[String]) -> Self {
+        let mut parsed = PushArgs::default();
+        let mut positionals = Vec::new();
+        let mut iter = args.iter();
+        let mut only_positionals = false;
+
+        while let Some(arg) = iter.next() {
+            if only_positionals || !arg.starts_with('-') || arg == "-" {
+                positionals.push(arg.clone());
+                continue;
+            }
+
+            match arg.as_str() {
+                "--" => only_positionals = true,
+                "--delete" => parsed.delete = true,
+                "--all" | "--branches" | "--mirror" | "--tags" | "--follow-tags" => {
+                    parsed.bulk = true
+                }
+                // Options whose value is given as the next argument
+                "-o" | "--push-option" | "--receive-pack" | "--exec" => {
+                    iter.next();
+                }
+                "--repo" => parsed.repository = iter.next().cloned(),
+                _ => {
+                    if let Some(repo) = arg.strip_prefix("--repo=") {
+                        parsed.repository = Some(repo.to_string());
+                    } else if !arg.starts_with("--") {
+                        // Bundled short flags such as `-fd`, up to `-o` whose
+                        // value is the rest of the argument or the next one
+                        for (idx, flag) in arg.char_indices().skip(1) {
+                            match flag {
+                                'd' => parsed.delete = true,
+                                'o' => {
+                                    if idx + 1 == arg.len() {
+                                        iter.next();
+                                    }
+                                    break;
+                                }
+                                _ => {}
+                            }
+                        }
+                    }
+                }
+            }
+        }
+
+        let mut positionals = positionals.into_iter();
+        if let Some(repository) = positionals.next() {
+            parsed.repository = Some(repository);
+        }
+        parsed.refspecs = positionals.collect();
+
+        parsed
+    }
+}
+
+/// Where a push is going: the remote, its URL and the destination refs.
+#[derive(Debug)]
+pub struct PushTarget {
+    pub remote: String,
+    pub remote_url: String,
+    pub refs: Vec<String>,
+}
+
+impl PushTarget {
+    /// Resolve the remote and destination refs the way `git push` would.
+    pub fn resolve(git_args: &[String]) -> Result<Self, Box<dyn std::error::Error>> {
+        let args = PushArgs::parse(git_args);
+        let branch = current_branch()?;
+
+        let remote = args
+            .repository
+            .clone()
+            .or_else(|| default_remote(branch.as_deref()))
+            .unwrap_or_else(|| "origin".to_string());
+        let remote_url = remote_url(&remote)?;
+
+        let refspecs = if args.refspecs.is_empty() {
+            config_get_all(&format!("remote.{}.push", remote))
+        } else {
+            args.refspecs.clone()
+        };
+
+        let push_default = config_get("push.default");
+        if args.bulk
+            || refspecs.iter().any(|refspec| is_pattern_refspec(refspec))
+            || (refspecs.is_empty() && push_default.as_deref() == Some("matching"))
+        {
+            return Ok(Self {
+                refs: dry_run_destinations(git_args)?,
+                remote,
+                remote_url,
+            });
+        }
+
+        let refs = if refspecs.is_empty() {
+            let branch = branch.ok_or("Not on a branch, specify the ref to push")?;
+            vec![implicit_destination(
+                &remote,
+                &branch,
+                push_default.as_deref(),
+            )]
+        } else {
+            let mut refs = Vec::new();
+            for refspec in &refspecs {
+                if let Some(dst) = refspec_destination(refspec, args.delete, branch.as_deref()) {
+                    if !refs.contains(&dst) {
+                        refs.push(dst);
+                    }
+                }
+            }
+            refs
+        };
+
+        Ok(Self {
+            remote,
+            remote_url,
+            refs,
+        })
+    }
+}
+
+/// Destination refs git would update, from `git push --dry-run --porcelain`.
+///
+/// Up-to-date, rejected and deleted refs are skipped since nothing lands on
+/// them, as are refs outside `refs/heads/` and `refs/tags/`. The pre-push
+/// hook is skipped, it would check the push a second time.
+fn dry_run_destinations(git_args: &[String]) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let output = Command::new("git")
+        .args(["push", "--dry-run", "--porcelain", "--no-verify"])
+        .args(git_args)
+        .output()?;
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    if !output.status.success() && !stdout.lines().any(|line| line == "Done") {
+        return Err(format!(
+            "Unable to list refs to push: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+        .into());
+    }
+
+    Ok(porcelain_destinations(&stdout))
+}
+
+/// Destination refs of the `<flag>\t<src>:<dst>\t<summary>` lines of
+/// `git push --porcelain` output that update or create a ref.
+fn porcelain_destinations(stdout: &str) -> Vec<String> {
+    let mut refs = Vec::new();
+    for line in stdout.lines() {
+        let mut fields = line.split('\t');
+        let (Some(flag), Some(refspec)) = (fields.next(), fields.next()) else {
+            continue;
+        };
+        if !matches!(flag, " " | "+" | "*") {
+            continue;
+        }
+        let Some((_, dst)) = refspec.split_once(':') else {
+            continue;
+        };
+        if dst.starts_with("refs/heads/") || dst.starts_with("refs/tags/") {
+            let dst = short_ref(dst).to_string();
+            if !refs.contains(&dst) {
+                refs.push(dst);
+            }
+        }
+    }
+
+    refs
+}
+
+/// Whether only git can tell where a refspec goes: wildcard refspecs and
+/// `:`, which pushes the matching branches.
+fn is_pattern_refspec(refspec: &str) -> bool {
+    refspec.contains('*') || refspec.trim_start_matches('+') == ":"
+}
+
+/// Remote used by a bare `git push` from the given branch.
+fn default_remote(branch: Option<&str>) -> Option<String> {
+    branch
+        .and_then(|b| config_get(&format!("branch.{}.pushRemote", b)))
+        .or_else(|| config_get("remote.pushDefault"))
+        .or_else(|| branch.and_then(|b| config_get(&format!("branch.{}.remote", b))))
+}
+
+/// Push URL for a configured remote name, or the argument itself if it is
+/// already a URL, with `pushInsteadOf`/`insteadOf` rewrites applied like git.
+fn remote_url(remote: &str) -> Result<String, Box<dyn std::error::Error>> {
+    // pushInsteadOf is ignored for remotes with an explicit pushurl
+    if let Some(pushurl) = config_get(&format!("remote.{}.pushurl", remote)) {
+        return Ok(rewrite_url(&pushurl, &url_rewrites("insteadof")).unwrap_or(pushurl));
+    }
+
+    let url = match config_get(&format!("remote.{}.url", remote)) {
+        Some(url) => url,
+        None if remote.contains(':') || remote.contains('/') => remote.to_string(),
+        None => return Err(format!("Remote '{}' not found in git config", remote).into()),
+    };
+
+    Ok(rewrite_url(&url, &url_rewrites("pushinsteadof"))
+        .or_else(|| rewrite_url(&url, &url_rewrites("insteadof")))
+        .unwrap_or(url))
+}
+
+/// `url.<base>.insteadOf` or `url.<base>.pushInsteadOf` rules as
+/// `(base, prefix)` pairs.
+fn url_rewrites(kind: &str) -> Vec<(String, String)> {
+    let Ok(entries) = run(&["config", "--get-regexp", &format!(r"^url\..*\.{}$", kind)]) else {
+        return Vec::new();
+    };
+
+    entries
+        .lines()
+        .filter_map(|entry| {
+            let (key, prefix) = entry.split_once(' ')?;
+            let base = key
+                .strip_prefix("url.")?
+                .strip_suffix(&format!(".{}", kind))?;
+            Some((base.to_string(), prefix.to_string()))
+        })
+        .collect()
+}
+
+/// Destination branch of a push without refspecs, following `push.default`.
+fn implicit_destination(remote: &str, branch: &str, push_default: Option<&str>) -> String {
+    let push_default = push_default.unwrap_or("simple");
+
+    if matches!(push_default, "upstream" | "tracking") {
+        let upstream_remote = config_get(&format!("branch.{}.remote", branch));
+        if upstream_remote.as_deref() == Some(remote) {
+            if let Some(merge) = config_get(&format!("branch.{}.merge", branch)) {
+                return short_ref(&merge).to_string();
+            }
+        }
+    }
+
+    branch.to_string()
+}
+
+/// Destination ref of a single refspec, `None` for deletions.
+fn refspec_destination(refspec: &str, delete: bool, branch: Option<&str>) -> Option<String> {
+    if delete {
+        return None;
+    }
+
+    let refspec = refspec.trim_start_matches('+');
+    let (src, dst) = match refspec.split_once(':') {
+        Some((src, dst)) => (src, dst),
+        None => (refspec, refspec),
+    };
+
+    if src.is_empty() || dst.is_empty() {
+        return None;
+    }
+
+    let dst = match dst {
+        "HEAD" | "@" => branch?,
+        dst => dst,
+    };
+
+    Some(short_ref(dst).to_string())
+}
+
+/// Strip `refs/heads/` or `refs/tags/` to get the ref name GitLab uses.
+pub fn short_ref(full_ref: &str) -> &str {
+    full_ref
+        .strip_prefix("refs/heads/")
+        .or_else(|| full_ref.strip_prefix("refs/tags/"))
+        .unwrap_or(full_ref)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    fn push_args(
+        repository: Option<&str>,
+        refspecs: &[&str],
+        delete: bool,
+        bulk: bool,
+    ) -> PushArgs {
+        PushArgs {
+            repository: repository.map(|repository| repository.to_string()),
+            refspecs: args(refspecs),
+            delete,
+            bulk,
+        }
+    }
+
+    #[test]
+    fn parses_push_arguments() {
+        let cases = [
+            (vec![], push_args(None, &[], false, false)),
+            (
+                vec!["upstream", "HEAD:release/1.2"],
+                push_args(Some("upstream"), &["HEAD:release/1.2"], false, false),
+            ),
+            (
+                vec!["--force-with-lease", "origin", "main", "+dev:dev"],
+                push_args(Some("origin"), &["main", "+dev:dev"], false, false),
+            ),
+            (
+                vec!["-fd", "origin", "old"],
+                push_args(Some("origin"), &["old"], true, false),
+            ),
+            (
+                vec!["--delete", "origin", "old"],
+                push_args(Some("origin"), &["old"], true, false),
+            ),
+            (
+                vec!["--repo=fork", "-f"],
+                push_args(Some("fork"), &[], false, false),
+            ),
+            // Option values are not positionals, and like git the
+            // repository argument wins over --repo
+            (
+                vec!["-o", "ci.skip", "--repo", "fork", "main"],
+                push_args(Some("main"), &[], false, false),
+            ),
+            // `-o` takes the rest of the argument, or the next one
+            (
+                vec!["-omerge_request.description=x", "origin", "HEAD"],
+                push_args(Some("origin"), &["HEAD"], false, false),
+            ),
+            (
+                vec!["-fomerge_request.target=develop", "origin", "HEAD"],
+                push_args(Some("origin"), &["HEAD"], false, false),
+            ),
+            (
+                vec!["-do", "ci.skip", "origin", "old"],
+                push_args(Some("origin"), &["old"], true, false),
+            ),
+            (
+                vec!["--tags", "--", "origin", "-odd"],
+                push_args(Some("origin"), &["-odd"], false, true),
+            ),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(PushArgs::parse(&args(&input)), expected, "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn resolves_refspec_destinations() {
+        let cases = [
+            ("main", false, Some("feature"), Some("main")),
+            (
+                "HEAD:release/1.2",
+                false,
+                Some("feature"),
+                Some("release/1.2"),
+            ),
+            ("+src:dst", false, None, Some("dst")),
+            ("feature:HEAD", false, Some("current"), Some("current")),
+            ("refs/heads/a:refs/tags/v1", false, None, Some("v1")),
+            // Deletions land nothing
+            (":dst", false, None, None),
+            ("old", true, None, None),
+            // Detached HEAD
+            ("HEAD", false, None, None),
+        ];
+
+        for (refspec, delete, branch, expected) in cases {
+            assert_eq!(
+                refspec_destination(refspec, delete, branch).as_deref(),
+                expected,
+                "{}",
+                refspec
+            );
+        }
+    }
+
+    #[test]
+    fn detects_refspecs_only_git_can_resolve() {
+        for refspec in [
+            "refs/heads/*:refs/heads/*",
+            "+refs/tags/*:refs/tags/*",
+            ":",
+            "+:",
+        ] {
+            assert!(is_pattern_refspec(refspec), "{}", refspec);
+        }
+        for refspec in ["main", "HEAD:refs/heads/main", ":old"] {
+            assert!(!is_pattern_refspec(refspec), "{}", refspec);
+        }
+    }
+
+    #[test]
+    fn reads_destinations_from_porcelain_output() {
+        let stdout = [
+            "To git@gitlab.com:group/proj.git",
+            " \trefs/heads/main:refs/heads/main\t1a2b3c..4d5e6f",
+            "+\trefs/heads/wip:refs/heads/wip\t+ 1a2b3c...4d5e6f (forced update)",
+            "*\trefs/tags/v1.0:refs/tags/v1.0\t[new tag]",
+            "-\t:refs/heads/old\t[deleted]",
+            "=\trefs/heads/dev:refs/heads/dev\t[up to date]",
+            "!\trefs/heads/x:refs/heads/x\t[rejected] (non-fast-forward)",
+            "*\trefs/notes/commits:refs/notes/commits\t[new reference]",
+            " \trefs/heads/main:refs/heads/main\t1a2b3c..4d5e6f",
+            "Done",
+        ]
+        .join("\n");
+
+        assert_eq!(
+            porcelain_destinations(&stdout),
+            args(&["main", "wip", "v1.0"])
+        );
+    }
+
+    #[test]
+    fn implicit_destination_is_the_branch_outside_upstream_mode() {
+        for push_default in [None, Some("simple"), Some("current")] {
+            assert_eq!(
+                implicit_destination("origin", "feature", push_default),
+                "feature"
+            );
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::env;
+use std::fs;
+use std::io::{self, BufRead};
+use std::path::{Path, PathBuf};
+
+use crate::git;
+
+/// Marker line identifying hooks written by `hook install`.
+const HOOK_MARKER: &str = "# Installed by gitlab-safe-push";
+
+/// Set on `git push` when the wrapper already checked pipelines, so the hook skips.
+pub const CHECKED_ENV: &str = "GITLAB_SAFE_PUSH_CHECKED";
+
+/// Directory git runs hooks from, honoring `core.hooksPath`.
+fn hooks_dir() -> Result<PathBuf, Box<dyn std::error::Error>> {
+    if let Some(hooks_path) = git::config_get("core.hooksPath") {
+        let hooks_path = PathBuf::from(hooks_path);
+        if hooks_path.is_absolute() {
+            return Ok(hooks_path);
+        }
+        // Relative hooks paths are resolved from the working tree root
+        let toplevel = git::run(&["rev-parse", "--show-toplevel"])?;
+        return Ok(Path::new(&toplevel).join(hooks_path));
+    }
+
+    let hooks = git::run(&["rev-parse", "--git-path", "hooks"])?;
+    Ok(PathBuf::from(hooks))
+}
+
+/// Write a pre-push hook that runs this binary in hook mode.
+///
+/// An existing hook not written by us is only replaced with `force`.
+pub fn install(force: bool) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    let dir = hooks_dir()?;
+    let hook_path = dir.join("pre-push");
+
+    if let Ok(existing) = fs::read_to_string(&hook_path) {
+        if !existing.contains(HOOK_MARKER) && !force {
+            return Err(format!(
+                "{} already exists, use --force to replace it",
+                hook_path.display()
+            )
+            .into());
+        }
+    }
+
+    let exe = env::current_exe()?;
+    let script = format!(
+        "#!/bin/sh\n{}\nexec '{}' hook run \"$@\"\n",
+        HOOK_MARKER,
+        exe.display().to_string().replace('\'', r"'\''")
+    );
+
+    fs::create_dir_all(&dir)?;
+    fs::write(&hook_path, script)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&hook_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    Ok(hook_path)
+}
+
+/// Destination refs of the push the hook is run for, read from stdin.
+pub fn read_pushed_refs() -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    pushed_refs(io::stdin().lock())
+}
+
+/// Destination refs from the `<local ref> <local sha> <remote ref> <remote sha>`
+/// lines git feeds a pre-push hook; deletions are skipped.
+fn pushed_refs(input: impl BufRead) -> Result<Vec<String>, Box<dyn std::error::Error>> {
+    let mut refs = Vec::new();
+
+    for line in input.lines() {
+        let line = line?;
+        let fields: Vec<&str> = line.split_whitespace().collect();
+        let [_local_ref, local_sha, remote_ref, _remote_sha] = fields[..] else {
+            continue;
+        };
+
+        if local_sha.bytes().all(|b| b == b'0') {
+            continue;
+        }
+
+        let remote_ref = git::short_ref(remote_ref).to_string();
+        if !refs.contains(&remote_ref) {
+            refs.push(remote_ref);
+        }
+    }
+
+    Ok(refs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ZERO: &str = "0000000000000000000000000000000000000000";
+    const SHA: &str = "1c002dd4b536e7479fe34593e72e6c6c1819e53b";
+
+    #[test]
+    fn reads_pushed_refs_from_hook_input() {
+        let cases = [
+            (vec![], vec![]),
+            (
+                vec![
+                    format!("refs/heads/feature {} refs/heads/main {}", SHA, SHA),
+                    format!("refs/tags/v1 {} refs/tags/v1 {}", SHA, ZERO),
+                ],
+                vec!["main", "v1"],
+            ),
+            // Deletions land nothing
+            (
+                vec![format!("(delete) {} refs/heads/old {}", ZERO, SHA)],
+                vec![],
+            ),
+            // The same destination from two sources, and lines git never sends
+            (
+                vec![
+                    format!("refs/heads/a {} refs/heads/main {}", SHA, SHA),
+                    format!("refs/heads/b {} refs/heads/main {}", SHA, SHA),
+                    "garbage".to_string(),
+                    String::new(),
+                ],
+                vec!["main"],
+            ),
+        ];
+
+        for (lines, expected) in cases {
+            let input = lines.join("\n");
+            let refs = pushed_refs(input.as_bytes()).unwrap();
+            assert_eq!(refs, expected, "{:?}", lines);
+        }
+    }
+}
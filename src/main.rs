@@ -1,23 +1,57 @@
-use chrono::{DateTime, Utc};
-use clap::Parser;
+use chrono::{DateTime, Local, Utc};
+use clap::builder::BoolishValueParser;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory, FromArgMatches, Parser, Subcommand};
 use colored::*;
-use regex::Regex;
 use reqwest::Client;
-use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::env;
-use std::fs;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, IsTerminal, Write};
 use std::process::{Command, ExitCode};
+use std::sync::Mutex;
 use std::time::Duration;
-use tokio::time::sleep;
+use tokio::time::{sleep, timeout_at, Instant};
 use url::Url;
 
+mod blackout;
+mod config;
+mod dag;
+mod eta;
+mod freeze;
+mod git;
+mod hook;
+mod pattern;
+mod poll;
+mod remote;
+mod rules;
+mod status;
+
+use blackout::Window;
+use config::{Config, MergeTrainAction, WatchTarget};
+use dag::PipelineGraph;
+use eta::JobHistory;
+use freeze::FreezePeriod;
+use git::PushTarget;
+use pattern::{JobPattern, Pattern};
+use poll::Polling;
+use remote::RemoteUrl;
+use rules::{Action, JobFacts, PipelineFacts, RuleSet};
+use status::{Status, StatusPolicy};
+
 #[derive(Parser)]
 #[command(name = "gitlab-safe-push")]
 #[command(about = "Check GitLab pipelines before pushing to prevent breaking CI/CD")]
 #[command(version)]
 struct Cli {
-    /// Arguments for git push
+    #[command(subcommand)]
+    command: Option<Commands>,
+
+    /// Arguments for git push, options included (e.g. `origin --tags`).
+    /// Everything from the first one on is passed to git
+    #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
     git_args: Vec<String>,
 
     /// Wait for pipelines to complete before pushing
@@ -36,199 +70,677 @@ struct Cli {
     #[arg(long, env("GITLAB_URL"))]
     gitlab_url: Option<String>,
 
-    /// Check interval in seconds
-    #[arg(long, default_value = "30")]
-    check_interval: u64,
+    /// Check interval in seconds (default: 30)
+    #[arg(long)]
+    check_interval: Option<u64>,
 
-    /// Stage name that blocks pushes (e.g., "deploy")
+    /// Stage name or pattern that blocks pushes (e.g., "deploy", "deploy-*")
     #[arg(long, env("GITLAB_BLOCKING_STAGE"))]
     blocking_stage: Option<String>,
 
-    /// Job names that block pushes, comma-separated (e.g., "terraform:dev,deploy:dev")
+    /// Job name patterns that block pushes, comma-separated (e.g., "terraform:*,deploy:dev*,tag:prod,env:production")
     #[arg(long, env("GITLAB_BLOCKING_JOBS"))]
     blocking_jobs: Option<String>,
 
+    /// Environment name patterns that block pushes while deploying, comma-separated (e.g., "staging,production")
+    #[arg(long, env("GITLAB_BLOCKING_ENVIRONMENTS"))]
+    blocking_environments: Option<String>,
+
     /// Seconds before blocking stage to start blocking (default: 15)
-    #[arg(long, default_value = "15")]
-    pre_block_duration: u64,
+    #[arg(long)]
+    pre_block_duration: Option<u64>,
 
     /// Seconds after blocking stage to resume allowing pushes (default: 5)
-    #[arg(long, default_value = "5")]
-    post_block_duration: u64,
+    #[arg(long)]
+    post_block_duration: Option<u64>,
+
+    /// Maximum number of pages fetched per GitLab list request (default: 10)
+    #[arg(long)]
+    max_pages: Option<u32>,
 
     /// Use simple mode: block on any running pipeline
     #[arg(long)]
     simple_mode: bool,
+
+    /// Give up waiting after this long (e.g., "15m", "1h 30m"), exiting with code 3
+    #[arg(long, env("GITLAB_SAFE_PUSH_TIMEOUT"))]
+    timeout: Option<String>,
+
+    /// Show how each blocking rule was evaluated
+    #[arg(long)]
+    explain: bool,
+
+    /// Wait for a deploy freeze to end instead of cancelling the push
+    #[arg(long)]
+    wait_for_freeze: bool,
+
+    /// This push fixes the branch: skip the failed pipeline check
+    #[arg(long, env("GITLAB_SAFE_PUSH_FIX"), value_parser = BoolishValueParser::new())]
+    fix: bool,
 }
 
-#[derive(Serialize, Deserialize, Default)]
-struct Config {
-    token: Option<String>,
-    gitlab_url: Option<String>,
-    blocking_stage: Option<String>,
-    blocking_jobs: Option<String>,
-    pre_block_duration: Option<u64>,
-    post_block_duration: Option<u64>,
-    check_interval: Option<u64>,
-    simple_mode: Option<bool>,
+#[derive(Subcommand)]
+enum Commands {
+    /// Manage the git pre-push hook
+    Hook {
+        #[command(subcommand)]
+        action: HookAction,
+    },
 }
 
-#[derive(Deserialize)]
+#[derive(Subcommand)]
+enum HookAction {
+    /// Install a pre-push hook so plain `git push` is checked too
+    Install {
+        /// Replace an existing pre-push hook
+        #[arg(long)]
+        force: bool,
+    },
+    /// Run the pipeline check as a pre-push hook (invoked by git)
+    Run {
+        /// Name of the remote being pushed to
+        remote: String,
+        /// URL of the remote being pushed to
+        url: String,
+    },
+}
+
+impl Cli {
+    /// The command line layer of the configuration (environment included),
+    /// and the token and URL taken from the environment, which rank below a
+    /// matching profile unlike the other variables.
+    fn config(&self, matches: &ArgMatches) -> (Config, Config) {
+        let from_env = |id: &str| matches.value_source(id) == Some(ValueSource::EnvVariable);
+        let mut config = Config {
+            token: self.token.clone(),
+            gitlab_url: self.gitlab_url.clone(),
+            blocking_stage: self.blocking_stage.clone(),
+            blocking_jobs: self.blocking_jobs.clone(),
+            blocking_environments: self.blocking_environments.clone(),
+            pre_block_duration: self.pre_block_duration,
+            post_block_duration: self.post_block_duration,
+            check_interval: self.check_interval,
+            max_pages: self.max_pages,
+            timeout: self.timeout.clone(),
+            simple_mode: self.simple_mode.then_some(true),
+            ..Config::default()
+        };
+
+        let env = Config {
+            token: config.token.take_if(|_| from_env("token")),
+            gitlab_url: config.gitlab_url.take_if(|_| from_env("gitlab_url")),
+            ..Config::default()
+        };
+        (config, env)
+    }
+}
+
+#[derive(Deserialize, Clone)]
 struct Pipeline {
     id: u64,
-    status: String,
-    r#ref: String,
-    created_at: String,
+    status: Status,
+    #[serde(rename = "ref", default)]
+    ref_name: Option<String>,
+    #[serde(default)]
+    source: Option<String>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 struct Job {
     id: u64,
     name: String,
     stage: String,
-    status: String,
+    status: Status,
     started_at: Option<String>,
     created_at: String,
+    /// Seconds the job ran, once it has run
+    #[serde(default)]
+    duration: Option<f64>,
+    #[serde(default)]
+    allow_failure: bool,
+    #[serde(default)]
+    tag_list: Vec<String>,
+    #[serde(default)]
+    environment: Option<JobEnvironment>,
+    /// Who started the job, or played it for a manual job
+    #[serde(default)]
+    user: Option<User>,
+}
+
+#[derive(Deserialize, Clone)]
+struct User {
+    username: String,
+    name: String,
+}
+
+#[derive(Deserialize, Clone)]
+struct JobEnvironment {
+    name: String,
+}
+
+/// A trigger job, possibly linked to the pipeline it started
+#[derive(Deserialize)]
+struct Bridge {
+    #[serde(flatten)]
+    job: Job,
+    downstream_pipeline: Option<DownstreamPipeline>,
+}
+
+#[derive(Deserialize)]
+struct DownstreamPipeline {
+    id: u64,
+    project_id: u64,
+    status: Status,
+    #[serde(rename = "ref", default)]
+    ref_name: Option<String>,
+    #[serde(default)]
+    source: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Project {
+    path_with_namespace: String,
+}
+
+#[derive(Deserialize)]
+struct MergeRequest {
+    iid: u64,
+    project_id: u64,
+    source_project_id: u64,
+}
+
+/// An entry of a merge train, with the pipeline testing it
+#[derive(Deserialize)]
+struct MergeTrainCar {
+    merge_request: MergeTrainRequest,
+    target_branch: String,
+    pipeline: Option<Pipeline>,
+}
+
+#[derive(Deserialize)]
+struct MergeTrainRequest {
+    iid: u64,
+}
+
+#[derive(Deserialize)]
+struct Environment {
+    name: String,
+}
+
+/// A deployment, with the pipeline of the job running it
+#[derive(Deserialize)]
+struct Deployment {
+    environment: Environment,
+    deployable: Option<Deployable>,
+}
+
+#[derive(Deserialize)]
+struct Deployable {
+    pipeline: DeployablePipeline,
+}
+
+#[derive(Deserialize)]
+struct DeployablePipeline {
+    id: u64,
+    status: Status,
+    #[serde(rename = "ref")]
+    ref_name: String,
+}
+
+/// Child and multi-project pipelines are followed this many levels deep
+const MAX_DOWNSTREAM_DEPTH: usize = 5;
+
+/// A GitLab request not answered within this is given up, `--timeout` or not
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Exit code when `--timeout` expires while still blocked
+const TIMEOUT_EXIT_CODE: u8 = 3;
+
+/// How the check of a push ended
+#[derive(Debug, PartialEq, Eq)]
+enum CheckOutcome {
+    Authorized,
+    Cancelled,
+    TimedOut,
+}
+
+/// Blocking pipelines found for one destination ref of the push, or
+/// for a watch target
+struct RefVerdict {
+    r#ref: String,
+    watched: bool,
+    blocking: Vec<(Option<Pipeline>, BlockingReason)>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 enum BlockingReason {
     SimpleMode,
-    BlockingStageRunning(String),
-    BlockingJobRunning(String),
-    PreBlockingStage(String, u64), // stage_name, seconds_running
+    Rule(String, String),                             // rule label, what matched
+    BlockingStageRunning(String, Vec<(String, u64)>), // stage_name, its running jobs and seconds_running
+    BlockingJobRunning(String, String, u64),          // job_name, matched pattern, seconds_running
+    PreBlockingStage(String, u64),                    // stage_name, seconds_running
+    PostBlockingStage(String, DateTime<Utc>),         // stage_name, end of the post-block window
+    PrerequisiteRunning(String, String, u64), // job_name, blocking_job_name, seconds_running
+    Downstream(String, String, String, Box<BlockingReason>), // bridge → pipeline, its project, ref, reason there
+    EnvironmentDeploying(String, String), // environment, ref of the deploying pipeline
+    DeployFreeze(DateTime<Utc>),          // end of the freeze
+    Blackout(String, Option<DateTime<Utc>>), // window, its end if foreseeable
+    BranchFailed(Vec<String>),            // failed jobs that count, all if empty
+    ManualJobWaiting(String),             // job_name
+    MergeTrain(u64, String, usize, usize), // merge request iid, target branch, position, train length
+}
+
+/// A long option of our own among the git arguments, where it would be
+/// passed to `git push` instead of applying (e.g. `origin main --no-wait`).
+fn misplaced_option(git_args: &[String]) -> Option<String> {
+    let command = Cli::command();
+    let ours: Vec<&str> = command
+        .get_arguments()
+        .filter_map(|arg| arg.get_long())
+        .collect();
+    git_args
+        .iter()
+        .take_while(|arg| *arg != "--")
+        .filter_map(|arg| arg.strip_prefix("--"))
+        .map(|arg| arg.split_once('=').map_or(arg, |(name, _)| name))
+        .find(|name| ours.contains(name))
+        .map(|name| format!("--{}", name))
+}
+
+/// Whether the remote is on the configured GitLab instance: a profile
+/// matched it, or it is on the host of `gitlab_url`.
+fn on_instance(config: &Config, remote: &RemoteUrl) -> bool {
+    config.profile.is_some()
+        || config
+            .gitlab_url
+            .as_deref()
+            .and_then(|url| Url::parse(url).ok())
+            .and_then(|url| {
+                url.host_str()
+                    .map(|host| host.eq_ignore_ascii_case(&remote.host))
+            })
+            .unwrap_or(false)
+}
+
+/// Ask a yes/no question on the terminal; no terminal means no.
+fn confirm(question: &str) -> bool {
+    if !io::stderr().is_terminal() {
+        return false;
+    }
+    eprint!("{} {} [y/N] ", "❓".bright_blue(), question);
+    let _ = io::stderr().flush();
+
+    // In the pre-push hook stdin carries the pushed refs, so read the
+    // terminal itself where there is one
+    let mut answer = String::new();
+    let read = match File::open("/dev/tty") {
+        Ok(tty) => BufReader::new(tty).read_line(&mut answer),
+        Err(_) => io::stdin().lock().read_line(&mut answer),
+    };
+    read.is_ok() && matches!(answer.trim().to_ascii_lowercase().as_str(), "y" | "yes")
+}
+
+/// `confirm` off the runtime, so the timeout still fires while the
+/// question waits. A plain thread rather than `spawn_blocking`, which the
+/// runtime would wait for on exit.
+async fn confirm_off_runtime(question: String) -> bool {
+    let (answer, answered) = tokio::sync::oneshot::channel();
+    std::thread::spawn(move || {
+        let _ = answer.send(confirm(&question));
+    });
+    answered.await.unwrap_or(false)
+}
+
+/// The train carrying one of the `own` merge requests, and its position
+/// from the front.
+fn train_position<'a>(
+    cars: &'a [MergeTrainCar],
+    own: &[u64],
+) -> Option<(Vec<&'a MergeTrainCar>, usize)> {
+    let car = cars
+        .iter()
+        .find(|car| own.contains(&car.merge_request.iid))?;
+    let train: Vec<&MergeTrainCar> = cars
+        .iter()
+        .filter(|other| other.target_branch == car.target_branch)
+        .collect();
+    let position = train
+        .iter()
+        .position(|other| other.merge_request.iid == car.merge_request.iid)?;
+    Some((train, position))
+}
+
+/// URL of the `rel="next"` entry of a `Link` header.
+fn parse_link_next(link: &str) -> Option<String> {
+    link.split(',').find_map(|entry| {
+        let (target, params) = entry.split_once(';')?;
+        params
+            .split(';')
+            .any(|param| param.trim() == r#"rel="next""#)
+            .then(|| {
+                target
+                    .trim()
+                    .trim_start_matches('<')
+                    .trim_end_matches('>')
+                    .to_string()
+            })
+    })
 }
 
 struct GitLabSafePush {
     client: Client,
     gitlab_url: String,
+    profile: Option<String>,
     token: String,
-    blocking_stage: Option<String>,
-    blocking_jobs: Vec<String>,
+    blocking_stage: Option<Pattern>,
+    blocking_jobs: Vec<JobPattern>,
+    blocking_environments: Vec<Pattern>,
     pre_block_duration: u64,
     post_block_duration: u64,
-    check_interval: u64,
+    polling: Polling,
+    max_pages: u32,
+    timeout: Option<Duration>,
+    eta_pipelines: u32,
     simple_mode: bool,
+    rules: RuleSet,
+    watch: Vec<(Option<String>, Pattern)>,
+    freeze_branches: Vec<Pattern>,
+    freeze_action: Action,
+    blackouts: Vec<Window>,
+    failed_branch_action: Action,
+    failed_branch_jobs: Vec<JobPattern>,
+    manual_jobs: Action,
+    merge_request_pipelines: bool,
+    merge_train_pipelines: bool,
+    merge_train: MergeTrainAction,
+    /// Whether the push may go ahead, per merge request found on a train
+    merge_train_answers: Mutex<HashMap<u64, bool>>,
+    /// Manual jobs seen waiting to be played, by job id: blocking ones, or
+    /// all of them with rules
+    manual_jobs_seen: Mutex<HashMap<u64, String>>,
+    pipeline_statuses: HashMap<Status, StatusPolicy>,
+    job_statuses: HashMap<Status, StatusPolicy>,
+    explain: bool,
+    fix: bool,
+    wait_for_freeze: bool,
+    graph_cache: Mutex<HashMap<u64, PipelineGraph>>,
+    /// Project paths by id, for downstream pipelines
+    project_cache: Mutex<HashMap<u64, String>>,
+    /// Sources of blocking conditions that could not be checked, reported once
+    unavailable: Mutex<HashSet<String>>,
+    /// Job durations by project and ref, `None` where they couldn't be read
+    history_cache: Mutex<HashMap<(String, String), Option<JobHistory>>>,
 }
 
 impl GitLabSafePush {
-    fn new(
-        gitlab_url: Option<String>,
-        token: Option<String>,
-        blocking_stage: Option<String>,
-        blocking_jobs: Option<String>,
-        pre_block_duration: u64,
-        post_block_duration: u64,
-        check_interval: u64,
-        simple_mode: bool,
-    ) -> Result<Self, Box<dyn std::error::Error>> {
-        let config = Self::load_config().unwrap_or_default();
-
-        let token = token
-            .or(config.token)
-            .or_else(|| env::var("GITLAB_TOKEN").ok())
-            .ok_or(
-                "GitLab token not found! Set GITLAB_TOKEN environment variable or use --token",
-            )?;
-
-        let gitlab_url = gitlab_url
-            .or(config.gitlab_url)
-            .or_else(|| env::var("GITLAB_URL").ok())
-            .ok_or(
-                "GitLab URL not found! Set GITLAB_URL environment variable or use --gitlab-url",
-            )?;
-
-        let blocking_stage = blocking_stage.or(config.blocking_stage);
-        let blocking_jobs_str = blocking_jobs.or(config.blocking_jobs);
-        let check_interval = if check_interval != 30 {
-            check_interval
-        } else {
-            config.check_interval.unwrap_or(30)
-        };
-
-        // Parse blocking jobs from comma-separated string
-        let blocking_jobs_vec: Vec<String> = blocking_jobs_str
-            .map(|jobs| {
-                jobs.split(',')
-                    .map(|job| job.trim().to_string())
-                    .filter(|job| !job.is_empty())
-                    .collect()
+    fn new(config: Config) -> Result<Self, Box<dyn std::error::Error>> {
+        let token = config.token.ok_or(
+            "GitLab token not found! Set GITLAB_TOKEN environment variable or use --token",
+        )?;
+
+        let gitlab_url = config.gitlab_url.ok_or(
+            "GitLab URL not found! Set GITLAB_URL environment variable or use --gitlab-url",
+        )?;
+
+        let timeout = config
+            .timeout
+            .as_deref()
+            .map(|timeout| {
+                humantime::parse_duration(timeout)
+                    .map_err(|e| format!("invalid timeout '{}': {}", timeout, e))
             })
-            .unwrap_or_default();
+            .transpose()?;
+
+        // The bounds only narrow an explicit interval when set explicitly
+        let interval = config.check_interval.unwrap_or(30);
+        let polling = Polling {
+            interval,
+            min: config.min_check_interval.unwrap_or(interval.min(5)),
+            max: config.max_check_interval.unwrap_or(interval.max(300)),
+        };
+        if polling.min > polling.max {
+            return Err("min_check_interval must not exceed max_check_interval".into());
+        }
 
-        // Determine mode: force advanced if blocking conditions are set
-        let has_blocking_config = blocking_stage.is_some() || !blocking_jobs_vec.is_empty();
+        let blocking_stage = config
+            .blocking_stage
+            .as_deref()
+            .map(Pattern::parse)
+            .transpose()?;
+
+        // Parse blocking job patterns from comma-separated string
+        let blocking_jobs_vec = config
+            .blocking_jobs
+            .as_deref()
+            .map(pattern::split_list)
+            .unwrap_or_default()
+            .iter()
+            .map(|job| JobPattern::parse(job))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let blocking_environments = config
+            .blocking_environments
+            .as_deref()
+            .map(pattern::split_list)
+            .unwrap_or_default()
+            .iter()
+            .map(|env| Pattern::parse(env))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let freeze_branches = config
+            .freeze_branches
+            .as_deref()
+            .map(pattern::split_list)
+            .unwrap_or_default()
+            .iter()
+            .map(|branch| Pattern::parse(branch))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let blackouts = config
+            .blackouts
+            .unwrap_or_default()
+            .iter()
+            .enumerate()
+            .map(|(idx, blackout)| Window::compile(blackout, idx))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let failed_branch_jobs = config
+            .failed_branch_jobs
+            .as_deref()
+            .map(pattern::split_list)
+            .unwrap_or_default()
+            .iter()
+            .map(|job| JobPattern::parse(job))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let rules = RuleSet::compile(config.rules.as_deref().unwrap_or_default())?;
+
+        let status_policies = |key: &str, policies: Option<BTreeMap<String, StatusPolicy>>| {
+            policies
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(status, policy)| {
+                    let status =
+                        Status::from_config(&status).map_err(|e| format!("{}: {}", key, e))?;
+                    Ok((status, policy))
+                })
+                .collect::<Result<HashMap<_, _>, String>>()
+        };
+        let pipeline_statuses = status_policies("pipeline_statuses", config.pipeline_statuses)?;
+        let job_statuses = status_policies("job_statuses", config.job_statuses)?;
+
+        let watch = config
+            .watch
+            .unwrap_or_default()
+            .into_iter()
+            .map(|target: WatchTarget| Ok((target.project, Pattern::parse(&target.ref_pattern)?)))
+            .collect::<Result<Vec<_>, String>>()?;
+
+        // Determine mode: force advanced if blocking conditions are set.
+        // Blocking environments are checked in every mode.
+        let has_blocking_config =
+            blocking_stage.is_some() || !blocking_jobs_vec.is_empty() || !rules.is_empty();
         let simple_mode = if has_blocking_config {
             false // Force advanced mode if blocking conditions are configured
         } else {
-            simple_mode || config.simple_mode.unwrap_or(true) // Default to simple mode
+            config.simple_mode.unwrap_or(true) // Default to simple mode
         };
 
         Ok(Self {
-            client: Client::new(),
+            client: Client::builder().timeout(REQUEST_TIMEOUT).build()?,
             gitlab_url: gitlab_url.trim_end_matches('/').to_string(),
+            profile: config.profile,
             token,
             blocking_stage,
             blocking_jobs: blocking_jobs_vec,
-            pre_block_duration,
-            post_block_duration,
-            check_interval,
+            blocking_environments,
+            pre_block_duration: config.pre_block_duration.unwrap_or(15),
+            post_block_duration: config.post_block_duration.unwrap_or(5),
+            polling,
+            max_pages: config.max_pages.unwrap_or(10).max(1),
+            timeout,
+            eta_pipelines: config.eta_pipelines.unwrap_or(5),
             simple_mode,
+            rules,
+            watch,
+            freeze_branches,
+            freeze_action: config.freeze_action.unwrap_or(Action::Block),
+            blackouts,
+            failed_branch_action: config.failed_branch.unwrap_or(Action::Allow),
+            failed_branch_jobs,
+            manual_jobs: config.manual_jobs.unwrap_or(Action::Allow),
+            merge_request_pipelines: config.merge_request_pipelines.unwrap_or(true),
+            merge_train_pipelines: config.merge_train_pipelines.unwrap_or(false),
+            merge_train: config.merge_train.unwrap_or(MergeTrainAction::Allow),
+            merge_train_answers: Mutex::new(HashMap::new()),
+            manual_jobs_seen: Mutex::new(HashMap::new()),
+            pipeline_statuses,
+            job_statuses,
+            explain: false,
+            fix: false,
+            wait_for_freeze: false,
+            graph_cache: Mutex::new(HashMap::new()),
+            project_cache: Mutex::new(HashMap::new()),
+            unavailable: Mutex::new(HashSet::new()),
+            history_cache: Mutex::new(HashMap::new()),
         })
     }
 
-    fn load_config() -> Option<Config> {
-        let home = dirs::home_dir()?;
-        let config_path = home.join(".gitlab-safe-push-config.json");
+    /// Path the instance is served under, from the path of its URL.
+    fn instance_prefix(&self) -> String {
+        Url::parse(&self.gitlab_url)
+            .map(|url| url.path().trim_matches('/').to_string())
+            .unwrap_or_default()
+    }
 
-        let content = fs::read_to_string(config_path).ok()?;
-        serde_json::from_str(&content).ok()
+    /// GET a list endpoint, following `Link: rel="next"` or `X-Next-Page`
+    /// until the last page or `max_pages` pages have been fetched.
+    async fn get_paginated<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        params: &HashMap<&str, &str>,
+    ) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+        self.get_paginated_while(url, params, |_| true).await
     }
 
-    fn run_git_command(&self, args: &[&str]) -> Result<String, Box<dyn std::error::Error>> {
-        let output = Command::new("git").args(args).output()?;
+    /// Like `get_paginated`, stopping early after a page for which `more`
+    /// says later pages aren't needed.
+    async fn get_paginated_while<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        params: &HashMap<&str, &str>,
+        more: impl Fn(&[T]) -> bool,
+    ) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+        let mut items = Vec::new();
+        let mut next_url: Option<String> = None;
+        let mut page = "1".to_string();
+
+        for _ in 0..self.max_pages {
+            // Link URLs already carry every query parameter
+            let request = match &next_url {
+                Some(next_url) => self.client.get(next_url),
+                None => self.client.get(url).query(params).query(&[("page", &page)]),
+            };
+
+            let response = request.header("PRIVATE-TOKEN", &self.token).send().await?;
+
+            if !response.status().is_success() {
+                return Err(format!(
+                    "GitLab API error: {} - {}",
+                    response.status(),
+                    response.text().await?
+                )
+                .into());
+            }
 
-        if !output.status.success() {
-            return Err(format!(
-                "Git command failed: {}",
-                String::from_utf8_lossy(&output.stderr)
-            )
-            .into());
+            let link_next = response
+                .headers()
+                .get("link")
+                .and_then(|link| link.to_str().ok())
+                .and_then(parse_link_next);
+            let next_page = response
+                .headers()
+                .get("x-next-page")
+                .and_then(|next| next.to_str().ok())
+                .map(|next| next.trim().to_string())
+                .filter(|next| !next.is_empty());
+
+            let page_items: Vec<T> = response.json().await?;
+            let wanted = more(&page_items);
+            items.extend(page_items);
+
+            match (link_next, next_page) {
+                _ if !wanted => return Ok(items),
+                (Some(link), _) => next_url = Some(link),
+                (None, Some(next)) => page = next,
+                (None, None) => return Ok(items),
+            }
         }
 
-        Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
-    }
-
-    fn get_current_branch(&self) -> Result<String, Box<dyn std::error::Error>> {
-        self.run_git_command(&["rev-parse", "--abbrev-ref", "HEAD"])
-    }
-
-    fn get_remote_url(&self) -> Result<String, Box<dyn std::error::Error>> {
-        self.run_git_command(&["config", "--get", "remote.origin.url"])
+        println!(
+            "{} Stopped after {} pages of {}, results may be incomplete (raise max_pages)",
+            "⚠️".yellow(),
+            self.max_pages,
+            url
+        );
+        Ok(items)
     }
 
-    fn parse_gitlab_project(&self, remote_url: &str) -> Option<String> {
-        if remote_url.starts_with("git@") {
-            let re = Regex::new(r"git@[^:]+:(.+)\.git").ok()?;
-            if let Some(caps) = re.captures(remote_url) {
-                return Some(caps.get(1)?.as_str().to_string());
-            }
-        }
+    /// GET the first page of a list endpoint, for requests that only need
+    /// the most recent items.
+    async fn get_page<T: DeserializeOwned>(
+        &self,
+        url: &str,
+        params: &[(&str, &str)],
+    ) -> Result<Vec<T>, Box<dyn std::error::Error>> {
+        let response = self
+            .client
+            .get(url)
+            .query(params)
+            .header("PRIVATE-TOKEN", &self.token)
+            .send()
+            .await?;
 
-        if let Ok(url) = Url::parse(remote_url) {
-            let path = url.path().trim_start_matches('/').trim_end_matches(".git");
-            if !path.is_empty() {
-                return Some(path.to_string());
-            }
+        if !response.status().is_success() {
+            return Err(format!(
+                "GitLab API error: {} - {}",
+                response.status(),
+                response.text().await?
+            )
+            .into());
         }
 
-        None
+        Ok(response.json().await?)
     }
 
+    /// Pipelines of a project in a status worth checking, on one branch or
+    /// all. The API filters on a single status, so pipelines are listed by
+    /// last update instead: pipelines still going are updated as their jobs
+    /// progress, and a page with none of them ends the listing.
     async fn get_project_pipelines(
         &self,
         project_path: &str,
-        branch: &str,
+        branch: Option<&str>,
     ) -> Result<Vec<Pipeline>, Box<dyn std::error::Error>> {
         let project_encoded = urlencoding::encode(project_path);
         let url = format!(
@@ -237,30 +749,19 @@ impl GitLabSafePush {
         );
 
         let mut params = HashMap::new();
-        params.insert("ref", branch);
-        params.insert("per_page", "5");
+        if let Some(branch) = branch {
+            params.insert("ref", branch);
+        }
+        params.insert("per_page", "100");
         params.insert("order_by", "updated_at");
         params.insert("sort", "desc");
 
-        let response = self
-            .client
-            .get(&url)
-            .header("PRIVATE-TOKEN", &self.token)
-            .query(&params)
-            .send()
+        let checked =
+            |pipeline: &Pipeline| self.pipeline_policy(&pipeline.status) != StatusPolicy::Ignore;
+        let pipelines: Vec<Pipeline> = self
+            .get_paginated_while(&url, &params, |page: &[Pipeline]| page.iter().any(checked))
             .await?;
-
-        if !response.status().is_success() {
-            return Err(format!(
-                "GitLab API error: {} - {}",
-                response.status(),
-                response.text().await?
-            )
-            .into());
-        }
-
-        let pipelines: Vec<Pipeline> = response.json().await?;
-        Ok(pipelines)
+        Ok(pipelines.into_iter().filter(checked).collect())
     }
 
     async fn get_pipeline_jobs(
@@ -274,218 +775,1608 @@ impl GitLabSafePush {
             self.gitlab_url, project_encoded, pipeline_id
         );
 
-        let response = self
-            .client
-            .get(&url)
-            .header("PRIVATE-TOKEN", &self.token)
-            .send()
-            .await?;
+        let mut params = HashMap::new();
+        params.insert("per_page", "100");
 
-        if !response.status().is_success() {
-            return Err(format!(
-                "GitLab API error: {} - {}",
-                response.status(),
-                response.text().await?
+        self.get_paginated(&url, &params).await
+    }
+
+    async fn get_pipeline_bridges(
+        &self,
+        project_path: &str,
+        pipeline_id: u64,
+    ) -> Result<Vec<Bridge>, Box<dyn std::error::Error>> {
+        let project_encoded = urlencoding::encode(project_path);
+        let url = format!(
+            "{}/api/v4/projects/{}/pipelines/{}/bridges",
+            self.gitlab_url, project_encoded, pipeline_id
+        );
+
+        let mut params = HashMap::new();
+        params.insert("per_page", "100");
+
+        self.get_paginated(&url, &params).await
+    }
+
+    async fn get_environments(
+        &self,
+        project_path: &str,
+    ) -> Result<Vec<Environment>, Box<dyn std::error::Error>> {
+        let project_encoded = urlencoding::encode(project_path);
+        let url = format!(
+            "{}/api/v4/projects/{}/environments",
+            self.gitlab_url, project_encoded
+        );
+
+        let mut params = HashMap::new();
+        params.insert("states", "available");
+        params.insert("per_page", "100");
+
+        self.get_paginated(&url, &params).await
+    }
+
+    async fn get_running_deployments(
+        &self,
+        project_path: &str,
+        environment: &str,
+    ) -> Result<Vec<Deployment>, Box<dyn std::error::Error>> {
+        let project_encoded = urlencoding::encode(project_path);
+        let url = format!(
+            "{}/api/v4/projects/{}/deployments",
+            self.gitlab_url, project_encoded
+        );
+
+        let mut params = HashMap::new();
+        params.insert("status", "running");
+        params.insert("environment", environment);
+        params.insert("per_page", "20");
+
+        self.get_paginated(&url, &params).await
+    }
+
+    /// The most recent pipeline on a branch that is done running.
+    async fn get_latest_finished_pipeline(
+        &self,
+        project_path: &str,
+        branch: &str,
+    ) -> Result<Option<Pipeline>, Box<dyn std::error::Error>> {
+        let project_encoded = urlencoding::encode(project_path);
+        let url = format!(
+            "{}/api/v4/projects/{}/pipelines",
+            self.gitlab_url, project_encoded
+        );
+
+        let pipelines: Vec<Pipeline> = self
+            .get_page(
+                &url,
+                &[
+                    ("ref", branch),
+                    ("scope", "finished"),
+                    ("order_by", "id"),
+                    ("sort", "desc"),
+                    ("per_page", "1"),
+                ],
             )
-            .into());
-        }
+            .await?;
+        Ok(pipelines.into_iter().next())
+    }
+
+    /// The last `count` successful pipelines on a ref, newest first.
+    async fn get_successful_pipelines(
+        &self,
+        project_path: &str,
+        r#ref: &str,
+        count: u32,
+    ) -> Result<Vec<Pipeline>, Box<dyn std::error::Error>> {
+        let project_encoded = urlencoding::encode(project_path);
+        let url = format!(
+            "{}/api/v4/projects/{}/pipelines",
+            self.gitlab_url, project_encoded
+        );
 
-        let jobs: Vec<Job> = response.json().await?;
-        Ok(jobs)
+        let count = count.to_string();
+        self.get_page(
+            &url,
+            &[
+                ("ref", r#ref),
+                ("status", "success"),
+                ("order_by", "id"),
+                ("sort", "desc"),
+                ("per_page", &count),
+            ],
+        )
+        .await
     }
 
-    fn parse_datetime(&self, datetime_str: &str) -> Option<DateTime<Utc>> {
-        DateTime::parse_from_rfc3339(datetime_str)
-            .map(|dt| dt.with_timezone(&Utc))
-            .ok()
+    async fn get_open_merge_requests(
+        &self,
+        project_path: &str,
+        source_branch: &str,
+    ) -> Result<Vec<MergeRequest>, Box<dyn std::error::Error>> {
+        let project_encoded = urlencoding::encode(project_path);
+        let url = format!(
+            "{}/api/v4/projects/{}/merge_requests",
+            self.gitlab_url, project_encoded
+        );
+
+        let mut params = HashMap::new();
+        params.insert("state", "opened");
+        params.insert("source_branch", source_branch);
+        params.insert("per_page", "20");
+
+        self.get_paginated(&url, &params).await
+    }
+
+    /// The most recent pipelines of a merge request. The endpoint can't
+    /// filter by status, and running pipelines are among the latest.
+    async fn get_merge_request_pipelines(
+        &self,
+        project_path: &str,
+        iid: u64,
+    ) -> Result<Vec<Pipeline>, Box<dyn std::error::Error>> {
+        let project_encoded = urlencoding::encode(project_path);
+        let url = format!(
+            "{}/api/v4/projects/{}/merge_requests/{}/pipelines",
+            self.gitlab_url, project_encoded, iid
+        );
+
+        self.get_page(&url, &[("per_page", "20")]).await
     }
 
-    fn seconds_since_start(&self, started_at: Option<&String>, created_at: &str) -> Option<u64> {
+    /// Active merge train entries of a project, front of each train first.
+    async fn get_merge_trains(
+        &self,
+        project_path: &str,
+    ) -> Result<Vec<MergeTrainCar>, Box<dyn std::error::Error>> {
+        let project_encoded = urlencoding::encode(project_path);
+        let url = format!(
+            "{}/api/v4/projects/{}/merge_trains",
+            self.gitlab_url, project_encoded
+        );
+
+        let mut params = HashMap::new();
+        params.insert("scope", "active");
+        params.insert("sort", "asc");
+        params.insert("per_page", "100");
+
+        self.get_paginated(&url, &params).await
+    }
+
+    async fn get_freeze_periods(
+        &self,
+        project_path: &str,
+    ) -> Result<Vec<FreezePeriod>, Box<dyn std::error::Error>> {
+        let project_encoded = urlencoding::encode(project_path);
+        let url = format!(
+            "{}/api/v4/projects/{}/freeze_periods",
+            self.gitlab_url, project_encoded
+        );
+
+        let mut params = HashMap::new();
+        params.insert("per_page", "100");
+
+        self.get_paginated(&url, &params).await
+    }
+
+    /// POST a GraphQL query and return its `data`.
+    async fn graphql(
+        &self,
+        query: &str,
+        variables: serde_json::Value,
+    ) -> Result<serde_json::Value, Box<dyn std::error::Error>> {
+        let url = format!("{}/api/graphql", self.gitlab_url);
+
+        let response = self
+            .client
+            .post(&url)
+            .bearer_auth(&self.token)
+            .json(&serde_json::json!({ "query": query, "variables": variables }))
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(format!(
+                "GitLab API error: {} - {}",
+                response.status(),
+                response.text().await?
+            )
+            .into());
+        }
+
+        let mut body: serde_json::Value = response.json().await?;
+        if let Some(errors) = body.get("errors") {
+            return Err(format!("GitLab GraphQL error: {}", errors).into());
+        }
+
+        Ok(body["data"].take())
+    }
+
+    /// Stages in pipeline order and job dependencies, from GraphQL.
+    async fn get_pipeline_graph(
+        &self,
+        project_path: &str,
+        pipeline_id: u64,
+    ) -> Result<Option<PipelineGraph>, Box<dyn std::error::Error>> {
+        let query = "query($fullPath: ID!, $id: CiPipelineID!) {
+            project(fullPath: $fullPath) {
+                pipeline(id: $id) {
+                    stages { nodes { name groups { nodes { jobs { nodes {
+                        name schedulingType needs { nodes { name } }
+                    } } } } } }
+                }
+            }
+        }";
+        let variables = serde_json::json!({
+            "fullPath": project_path,
+            "id": format!("gid://gitlab/Ci::Pipeline/{}", pipeline_id),
+        });
+
+        let data = self.graphql(query, variables).await?;
+        Ok(data
+            .pointer("/project/pipeline")
+            .and_then(PipelineGraph::from_graphql)
+            .filter(|graph| !graph.stages.is_empty()))
+    }
+
+    async fn get_project(&self, project: &str) -> Result<Project, Box<dyn std::error::Error>> {
+        let project_encoded = urlencoding::encode(project);
+        let url = format!("{}/api/v4/projects/{}", self.gitlab_url, project_encoded);
+
+        let response = self
+            .client
+            .get(&url)
+            .header("PRIVATE-TOKEN", &self.token)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            return Err(format!(
+                "GitLab API error: {} - {}",
+                response.status(),
+                response.text().await?
+            )
+            .into());
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Path of a project by id, cached per project. `None`, reported, when
+    /// the token can't read it.
+    async fn get_project_path(&self, project_id: u64) -> Option<String> {
+        if let Some(path) = self.project_cache.lock().unwrap().get(&project_id) {
+            return Some(path.clone());
+        }
+
+        match self.get_project(&project_id.to_string()).await {
+            Ok(project) => {
+                self.project_cache
+                    .lock()
+                    .unwrap()
+                    .insert(project_id, project.path_with_namespace.clone());
+                Some(project.path_with_namespace)
+            }
+            Err(e) => {
+                self.report_unavailable(&format!("downstream project {}", project_id), &*e);
+                None
+            }
+        }
+    }
+
+    /// Warn, once per source, that a source of blocking conditions could
+    /// not be checked. The other sources still decide.
+    fn report_unavailable(&self, source: &str, error: &dyn std::error::Error) {
+        if self.unavailable.lock().unwrap().insert(source.to_string()) {
+            println!(
+                "{} Unable to check {}, skipping it: {}",
+                "⚠️".yellow(),
+                source,
+                error
+            );
+        }
+    }
+
+    /// What an optional source found, nothing if it could not be checked.
+    fn or_unavailable<T: Default>(
+        &self,
+        source: &str,
+        result: Result<T, Box<dyn std::error::Error>>,
+    ) -> T {
+        result.unwrap_or_else(|e| {
+            self.report_unavailable(source, &*e);
+            T::default()
+        })
+    }
+
+    fn parse_datetime(&self, datetime_str: &str) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(datetime_str)
+            .map(|dt| dt.with_timezone(&Utc))
+            .ok()
+    }
+
+    fn seconds_since_start(&self, started_at: Option<&String>, created_at: &str) -> Option<u64> {
+        let now = Utc::now();
+        // A start a little ahead of our clock is a start just now
+        let elapsed = |since: DateTime<Utc>| (now - since).num_seconds().max(0) as u64;
+
+        if let Some(started) = started_at {
+            if let Some(start_time) = self.parse_datetime(started) {
+                return Some(elapsed(start_time));
+            }
+        }
+
+        self.parse_datetime(created_at).map(elapsed)
+    }
+
+    /// Stage order and job graph of a pipeline from GitLab, cached per
+    /// pipeline. `None` when unavailable, callers fall back to inference.
+    async fn get_cached_graph(
+        &self,
+        project_path: &str,
+        pipeline_id: u64,
+    ) -> Option<PipelineGraph> {
+        if let Some(graph) = self.graph_cache.lock().unwrap().get(&pipeline_id) {
+            return Some(graph.clone());
+        }
+
+        let graph = self
+            .get_pipeline_graph(project_path, pipeline_id)
+            .await
+            .ok()
+            .flatten()?;
+        self.graph_cache
+            .lock()
+            .unwrap()
+            .insert(pipeline_id, graph.clone());
+        Some(graph)
+    }
+
+    /// Durations of the jobs of the last `eta_pipelines` successful
+    /// pipelines on a ref, cached per project and ref, failures included so
+    /// polls don't request them again.
+    async fn get_job_history(&self, project_path: &str, r#ref: &str) -> Option<JobHistory> {
+        let key = (project_path.to_string(), r#ref.to_string());
+        if let Some(history) = self.history_cache.lock().unwrap().get(&key) {
+            return history.clone();
+        }
+
+        let history = self.fetch_job_history(project_path, r#ref).await;
+        self.history_cache
+            .lock()
+            .unwrap()
+            .insert(key, history.clone());
+        history
+    }
+
+    async fn fetch_job_history(&self, project_path: &str, r#ref: &str) -> Option<JobHistory> {
+        let pipelines = self
+            .get_successful_pipelines(project_path, r#ref, self.eta_pipelines)
+            .await
+            .ok()?;
+        let mut history = JobHistory::default();
+        for pipeline in pipelines {
+            let jobs = self
+                .get_pipeline_jobs(project_path, pipeline.id)
+                .await
+                .ok()?;
+            for job in jobs.iter().filter(|job| job.status == Status::Success) {
+                if let Some(duration) = job.duration {
+                    history.record(&job.name, duration);
+                }
+            }
+        }
+        Some(history)
+    }
+
+    /// When a running blocking job or stage, or a job a blocking job
+    /// needs, should be done, from how long the same jobs took recently on
+    /// the ref and how long they have been running. `None` for other
+    /// reasons or jobs without history.
+    async fn estimate_clear(
+        &self,
+        project_path: &str,
+        r#ref: &str,
+        reason: &BlockingReason,
+    ) -> Option<DateTime<Utc>> {
+        if self.eta_pipelines == 0 {
+            return None;
+        }
+
+        let remaining = match reason {
+            BlockingReason::BlockingJobRunning(name, _, seconds) => self
+                .get_job_history(project_path, r#ref)
+                .await?
+                .remaining(name, *seconds)?,
+            BlockingReason::BlockingStageRunning(_, running) => {
+                let history = self.get_job_history(project_path, r#ref).await?;
+                running
+                    .iter()
+                    .map(|(name, seconds)| history.remaining(name, *seconds))
+                    .collect::<Option<Vec<_>>>()?
+                    .into_iter()
+                    .max()?
+            }
+            // The blocking job has its whole run ahead of it
+            BlockingReason::PrerequisiteRunning(job, blocking_job, seconds) => {
+                let history = self.get_job_history(project_path, r#ref).await?;
+                history.remaining(job, *seconds)? + history.remaining(blocking_job, 0)?
+            }
+            BlockingReason::Downstream(_, project, r#ref, reason) => {
+                return Box::pin(self.estimate_clear(project, r#ref, reason)).await;
+            }
+            _ => return None,
+        };
+        Some(Utc::now() + chrono::Duration::seconds(remaining as i64))
+    }
+
+    /// Seconds a job has been running, zero while pending.
+    fn seconds_running(&self, job: &Job) -> u64 {
+        job.started_at
+            .as_deref()
+            .and_then(|started| self.parse_datetime(started))
+            .map_or(0, |started| {
+                (Utc::now() - started).num_seconds().max(0) as u64
+            })
+    }
+
+    /// Fallback stage order: jobs are created stage by stage, so the order
+    /// in which stages first appear by ascending job id follows the pipeline.
+    fn infer_stage_order(&self, jobs: &[Job]) -> Vec<String> {
+        let mut sorted: Vec<&Job> = jobs.iter().collect();
+        sorted.sort_by_key(|job| job.id);
+
+        let mut stages = Vec::new();
+        let mut stage_set = HashSet::new();
+
+        for job in sorted {
+            if stage_set.insert(job.stage.clone()) {
+                stages.push(job.stage.clone());
+            }
+        }
+
+        stages
+    }
+
+    fn find_stage_index(&self, stages: &[String], target_stage: &str) -> Option<usize> {
+        stages.iter().position(|s| s == target_stage)
+    }
+
+    /// The blocking job pattern matching a job, if any.
+    fn matching_job_pattern(&self, job: &Job) -> Option<&JobPattern> {
+        let environment = job.environment.as_ref().map(|env| env.name.as_str());
+        self.blocking_jobs
+            .iter()
+            .find(|pattern| pattern.matches(&job.name, &job.tag_list, environment))
+    }
+
+    fn is_blocking_stage(&self, stage: &str) -> bool {
+        self.blocking_stage
+            .as_ref()
+            .is_some_and(|pattern| pattern.matches(stage))
+    }
+
+    fn is_blocking_job(&self, job: &Job) -> bool {
+        self.matching_job_pattern(job).is_some() || self.is_blocking_stage(&job.stage)
+    }
+
+    fn pipeline_policy(&self, status: &Status) -> StatusPolicy {
+        self.pipeline_statuses
+            .get(status)
+            .copied()
+            .unwrap_or_else(|| status.default_pipeline_policy())
+    }
+
+    /// Whether a job in this status counts as running.
+    fn job_active(&self, status: &Status) -> bool {
+        let policy = self
+            .job_statuses
+            .get(status)
+            .copied()
+            .unwrap_or_else(|| status.default_job_policy());
+        policy == StatusPolicy::Active
+    }
+
+    /// Pipeline statuses that are checked rather than ignored, for display.
+    fn checked_pipeline_statuses(&self) -> Vec<Status> {
+        let unknown = self
+            .pipeline_statuses
+            .keys()
+            .filter(|status| matches!(status, Status::Unknown(_)))
+            .cloned();
+        Status::KNOWN
+            .into_iter()
+            .chain(unknown)
+            .filter(|status| self.pipeline_policy(status) != StatusPolicy::Ignore)
+            .collect()
+    }
+
+    async fn check_pipeline_blocking(
+        &self,
+        project_path: &str,
+        branch: &str,
+        pipeline: &Pipeline,
+    ) -> Result<Option<BlockingReason>, Box<dyn std::error::Error>> {
+        let facts = PipelineFacts {
+            r#ref: branch,
+            source: pipeline.source.as_deref(),
+        };
+        self.check_pipeline_tree(project_path, pipeline.id, &pipeline.status, &facts, 0)
+            .await
+    }
+
+    /// Check a pipeline's jobs and trigger jobs, then the downstream
+    /// pipelines they started, recursively. In simple mode only the
+    /// pipeline statuses matter.
+    async fn check_pipeline_tree(
+        &self,
+        project: &str,
+        pipeline_id: u64,
+        status: &Status,
+        facts: &PipelineFacts<'_>,
+        depth: usize,
+    ) -> Result<Option<BlockingReason>, Box<dyn std::error::Error>> {
+        if self.simple_mode && self.pipeline_policy(status) == StatusPolicy::Active {
+            return Ok(Some(BlockingReason::SimpleMode));
+        }
+
+        // Without its trigger jobs, the pipeline's own jobs still decide
+        let bridges = self.or_unavailable(
+            &format!("trigger jobs of {} #{}", project, pipeline_id),
+            self.get_pipeline_bridges(project, pipeline_id).await,
+        );
+        if !self.simple_mode {
+            let mut jobs = self.get_pipeline_jobs(project, pipeline_id).await?;
+            // A running trigger job counts for its stage like any other job
+            jobs.extend(bridges.iter().map(|bridge| bridge.job.clone()));
+            self.announce_played_jobs(pipeline_id, &jobs);
+            if self.rules.is_empty() {
+                let graph = self.get_cached_graph(project, pipeline_id).await;
+                let stages = match &graph {
+                    Some(graph) => graph.stages.clone(),
+                    None => self.infer_stage_order(&jobs),
+                };
+                if let Some(reason) = self.check_jobs_blocking(&jobs, &stages, graph.as_ref()) {
+                    return Ok(Some(reason));
+                }
+                if let Some(reason) = self.check_manual_jobs(pipeline_id, &jobs) {
+                    return Ok(Some(reason));
+                }
+            } else {
+                // Rules name no blocking jobs: any manual job may gate a deploy
+                self.record_manual_jobs(jobs.iter().filter(|job| job.status == Status::Manual));
+                if let Some(reason) = self.check_rules(pipeline_id, facts, &jobs) {
+                    return Ok(Some(reason));
+                }
+            }
+        }
+
+        if depth >= MAX_DOWNSTREAM_DEPTH {
+            return Ok(None);
+        }
+
+        for bridge in &bridges {
+            let Some(downstream) = &bridge.downstream_pipeline else {
+                continue;
+            };
+            if self.pipeline_policy(&downstream.status) == StatusPolicy::Ignore {
+                continue;
+            }
+
+            // GraphQL lookups need the project path rather than its id
+            let Some(project_path) = self.get_project_path(downstream.project_id).await else {
+                continue;
+            };
+            let downstream_facts = PipelineFacts {
+                r#ref: downstream.ref_name.as_deref().unwrap_or(facts.r#ref),
+                source: downstream.source.as_deref(),
+            };
+            // A downstream pipeline that can't be read is skipped, not the
+            // whole check with what it already found
+            let checked = Box::pin(self.check_pipeline_tree(
+                &project_path,
+                downstream.id,
+                &downstream.status,
+                &downstream_facts,
+                depth + 1,
+            ))
+            .await;
+            let blocking = self.or_unavailable(
+                &format!("downstream pipeline {} #{}", project_path, downstream.id),
+                checked,
+            );
+            if let Some(reason) = blocking {
+                let path = format!("{} → {} #{}", bridge.job.name, project_path, downstream.id);
+                return Ok(Some(BlockingReason::Downstream(
+                    path,
+                    project_path,
+                    downstream_facts.r#ref.to_string(),
+                    Box::new(reason),
+                )));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Apply the configured rules to a pipeline's jobs. Warnings are
+    /// printed here as they don't stop the push.
+    fn check_rules(
+        &self,
+        pipeline_id: u64,
+        facts: &PipelineFacts,
+        jobs: &[Job],
+    ) -> Option<BlockingReason> {
+        let job_facts: Vec<JobFacts> = jobs
+            .iter()
+            .map(|job| JobFacts {
+                name: &job.name,
+                stage: &job.stage,
+                status: job.status.clone(),
+                active: self.job_active(&job.status),
+                tags: &job.tag_list,
+                environment: job.environment.as_ref().map(|env| env.name.as_str()),
+                seconds_running: self.seconds_since_start(job.started_at.as_ref(), &job.created_at),
+            })
+            .collect();
+
+        let evaluation = self.rules.evaluate(facts, &job_facts);
+        if self.explain {
+            println!("{} Pipeline #{}:", "🔎".bright_blue(), pipeline_id);
+            for line in &evaluation.trace {
+                println!("    {}", line);
+            }
+        }
+
+        let verdict = evaluation.verdict?;
+        match verdict.action {
+            Action::Block => Some(BlockingReason::Rule(verdict.rule, verdict.detail)),
+            Action::Warn => {
+                println!(
+                    "{} Pipeline #{}: {} [{}]",
+                    "⚠️".yellow(),
+                    pipeline_id,
+                    verdict.detail,
+                    verdict.rule
+                );
+                None
+            }
+            Action::Allow => None,
+        }
+    }
+
+    /// Announce the manual jobs seen waiting by an earlier check that
+    /// have been played since, and by whom.
+    fn announce_played_jobs(&self, pipeline_id: u64, jobs: &[Job]) {
+        for job in self.played_jobs(jobs) {
+            let by = match &job.user {
+                Some(user) => format!(" by {} (@{})", user.name, user.username),
+                None => String::new(),
+            };
+            println!(
+                "{} Pipeline #{}: manual job '{}' was played{}",
+                "▶️".bright_blue(),
+                pipeline_id,
+                job.name,
+                by
+            );
+        }
+    }
+
+    /// Jobs seen waiting by an earlier check that are no longer manual,
+    /// forgotten once returned.
+    fn played_jobs<'a>(&self, jobs: &'a [Job]) -> Vec<&'a Job> {
+        let mut seen = self.manual_jobs_seen.lock().unwrap();
+        jobs.iter()
+            .filter(|job| job.status != Status::Manual && seen.remove(&job.id).is_some())
+            .collect()
+    }
+
+    /// Remember manual jobs waiting to be played, to announce who plays them.
+    fn record_manual_jobs<'a>(&self, waiting: impl Iterator<Item = &'a Job>) {
+        let mut seen = self.manual_jobs_seen.lock().unwrap();
+        for job in waiting {
+            seen.insert(job.id, job.name.clone());
+        }
+    }
+
+    /// Manual blocking jobs waiting to be played, blocking or warned about
+    /// depending on `manual_jobs`.
+    fn check_manual_jobs(&self, pipeline_id: u64, jobs: &[Job]) -> Option<BlockingReason> {
+        let waiting: Vec<&Job> = jobs
+            .iter()
+            .filter(|job| job.status == Status::Manual && self.is_blocking_job(job))
+            .collect();
+
+        self.record_manual_jobs(waiting.iter().copied());
+
+        match self.manual_jobs {
+            Action::Block => waiting
+                .first()
+                .map(|job| BlockingReason::ManualJobWaiting(job.name.clone())),
+            Action::Warn => {
+                for job in waiting {
+                    println!(
+                        "{} Pipeline #{}: manual job '{}' has not been played",
+                        "⚠️".yellow(),
+                        pipeline_id,
+                        job.name
+                    );
+                }
+                None
+            }
+            Action::Allow => None,
+        }
+    }
+
+    fn check_jobs_blocking(
+        &self,
+        jobs: &[Job],
+        stages: &[String],
+        graph: Option<&PipelineGraph>,
+    ) -> Option<BlockingReason> {
+        // Check specific jobs blocking
+        if !self.blocking_jobs.is_empty() {
+            for job in jobs {
+                if let Some(pattern) = self.matching_job_pattern(job) {
+                    if self.job_active(&job.status) {
+                        return Some(BlockingReason::BlockingJobRunning(
+                            job.name.clone(),
+                            pattern.to_string(),
+                            self.seconds_running(job),
+                        ));
+                    }
+                }
+            }
+        }
+
+        // Check if we're in the blocking stage
+        for job in jobs {
+            if self.job_active(&job.status) && self.is_blocking_stage(&job.stage) {
+                let running = jobs
+                    .iter()
+                    .filter(|other| other.stage == job.stage && self.job_active(&other.status))
+                    .map(|other| (other.name.clone(), self.seconds_running(other)))
+                    .collect();
+                return Some(BlockingReason::BlockingStageRunning(
+                    job.stage.clone(),
+                    running,
+                ));
+            }
+        }
+
+        // With the job graph known, pre/post-block follow `needs:`
+        if let Some(graph) = graph {
+            return self.check_needs_blocking(jobs, graph);
+        }
+
+        // Otherwise approximate with the stages around the blocking stage(s)
+        if self.blocking_stage.is_some() {
+            for job in jobs {
+                if !self.job_active(&job.status) {
+                    continue;
+                }
+                let Some(current_idx) = self.find_stage_index(stages, &job.stage) else {
+                    continue;
+                };
+                let Some(seconds_running) =
+                    self.seconds_since_start(job.started_at.as_ref(), &job.created_at)
+                else {
+                    continue;
+                };
+                let stage_blocks = |idx: Option<usize>| {
+                    idx.and_then(|idx| stages.get(idx))
+                        .is_some_and(|stage| self.is_blocking_stage(stage))
+                };
+
+                // We're in stage -1 of a blocking stage
+                if stage_blocks(Some(current_idx + 1)) && seconds_running >= self.pre_block_duration
+                {
+                    return Some(BlockingReason::PreBlockingStage(
+                        job.stage.clone(),
+                        seconds_running,
+                    ));
+                }
+
+                // We're in stage +1 of a blocking stage, check post-block timing
+                if stage_blocks(current_idx.checked_sub(1))
+                    && seconds_running < self.post_block_duration
+                {
+                    return Some(self.post_block(&job.stage, seconds_running));
+                }
+            }
+        }
+
+        None
+    }
+
+    fn post_block(&self, stage: &str, seconds_running: u64) -> BlockingReason {
+        let left = self.post_block_duration.saturating_sub(seconds_running);
+        BlockingReason::PostBlockingStage(
+            stage.to_string(),
+            Utc::now() + chrono::Duration::seconds(left as i64),
+        )
+    }
+
+    /// Pre-block: a running job that a blocking job still waiting to run
+    /// needs, directly or transitively, after `pre_block_duration`.
+    /// Post-block: a job that directly needs a finished blocking job, for
+    /// `post_block_duration` after it started.
+    fn check_needs_blocking(&self, jobs: &[Job], graph: &PipelineGraph) -> Option<BlockingReason> {
+        let blocking: Vec<&Job> = jobs
+            .iter()
+            .filter(|job| self.is_blocking_job(job))
+            .collect();
+        let running = jobs.iter().filter(|job| self.job_active(&job.status));
+
+        for job in running {
+            let Some(seconds_running) =
+                self.seconds_since_start(job.started_at.as_ref(), &job.created_at)
+            else {
+                continue;
+            };
+
+            if seconds_running >= self.pre_block_duration {
+                for blocking_job in &blocking {
+                    if blocking_job.status == Status::Created
+                        && graph
+                            .transitive_prerequisites(&blocking_job.name)
+                            .contains(job.name.as_str())
+                    {
+                        return Some(BlockingReason::PrerequisiteRunning(
+                            job.name.clone(),
+                            blocking_job.name.clone(),
+                            seconds_running,
+                        ));
+                    }
+                }
+            }
+
+            if seconds_running < self.post_block_duration {
+                let prerequisites = graph.prerequisites(&job.name);
+                if blocking
+                    .iter()
+                    .any(|blocking_job| prerequisites.contains(&blocking_job.name.as_str()))
+                {
+                    return Some(self.post_block(&job.stage, seconds_running));
+                }
+            }
+        }
+
+        None
+    }
+
+    async fn check_blocking_pipelines(
+        &self,
+        project_path: &str,
+        branch: &str,
+    ) -> Result<Vec<(Pipeline, BlockingReason)>, Box<dyn std::error::Error>> {
+        let mut blocking_pipelines = Vec::new();
+
+        for pipeline in self
+            .get_project_pipelines(project_path, Some(branch))
+            .await?
+        {
+            if let Some(reason) = self
+                .check_pipeline_blocking(project_path, branch, &pipeline)
+                .await?
+            {
+                blocking_pipelines.push((pipeline, reason));
+            }
+        }
+
+        Ok(blocking_pipelines)
+    }
+
+    /// Running pipelines of the open merge requests from the branch:
+    /// detached and merged results pipelines don't run on the branch ref.
+    async fn check_merge_request_pipelines(
+        &self,
+        project_path: &str,
+        branch: &str,
+    ) -> Result<Vec<(Pipeline, BlockingReason)>, Box<dyn std::error::Error>> {
+        let mut blocking_pipelines = Vec::new();
+        if !self.merge_request_pipelines {
+            return Ok(blocking_pipelines);
+        }
+
+        // Merge requests can be disabled for the project
+        let merge_requests = self.or_unavailable(
+            "merge request pipelines",
+            self.get_open_merge_requests(project_path, branch).await,
+        );
+        // Same-named branches of forks are someone else's
+        let own = merge_requests
+            .iter()
+            .filter(|mr| mr.source_project_id == mr.project_id);
+
+        for merge_request in own {
+            let pipelines = self.or_unavailable(
+                "merge request pipelines",
+                self.get_merge_request_pipelines(project_path, merge_request.iid)
+                    .await,
+            );
+
+            // Branch pipelines are listed too but were checked already
+            for pipeline in pipelines.into_iter().filter(|p| {
+                p.ref_name.as_deref() != Some(branch)
+                    && self.pipeline_policy(&p.status) != StatusPolicy::Ignore
+            }) {
+                // One unreadable pipeline must not drop what the others found
+                let blocking = self.or_unavailable(
+                    &format!("merge request !{} pipelines", merge_request.iid),
+                    self.check_pipeline_blocking(project_path, branch, &pipeline)
+                        .await,
+                );
+                if let Some(reason) = blocking {
+                    blocking_pipelines.push((pipeline, reason));
+                }
+            }
+        }
+
+        Ok(blocking_pipelines)
+    }
+
+    /// Pipelines of the merge trains into the branch: a push to it
+    /// restarts them all.
+    async fn check_merge_train_pipelines(
+        &self,
+        project_path: &str,
+        branch: &str,
+    ) -> Result<Vec<(Pipeline, BlockingReason)>, Box<dyn std::error::Error>> {
+        let mut blocking_pipelines = Vec::new();
+        if !self.merge_train_pipelines {
+            return Ok(blocking_pipelines);
+        }
+
+        // Merge trains need a paid tier: without them, there's no train
+        let cars = self.or_unavailable("merge trains", self.get_merge_trains(project_path).await);
+        let pipelines = cars
+            .into_iter()
+            .filter(|car| car.target_branch == branch)
+            .filter_map(|car| car.pipeline)
+            .filter(|pipeline| self.pipeline_policy(&pipeline.status) != StatusPolicy::Ignore);
+
+        for pipeline in pipelines {
+            let blocking = self.or_unavailable(
+                "merge train pipelines",
+                self.check_pipeline_blocking(project_path, branch, &pipeline)
+                    .await,
+            );
+            if let Some(reason) = blocking {
+                blocking_pipelines.push((pipeline, reason));
+            }
+        }
+
+        Ok(blocking_pipelines)
+    }
+
+    /// The pushed branch's merge request on a merge train: a push takes it
+    /// off the train and restarts every pipeline behind it. The train is
+    /// shown, and with `confirm` the user decides, the first time only.
+    async fn check_merge_train(
+        &self,
+        project_path: &str,
+        branch: &str,
+    ) -> Result<Option<(Option<Pipeline>, BlockingReason)>, Box<dyn std::error::Error>> {
+        if self.merge_train == MergeTrainAction::Allow {
+            return Ok(None);
+        }
+
+        let merge_requests = self.or_unavailable(
+            "merge trains",
+            self.get_open_merge_requests(project_path, branch).await,
+        );
+        let own: Vec<u64> = merge_requests
+            .iter()
+            .filter(|mr| mr.source_project_id == mr.project_id)
+            .map(|mr| mr.iid)
+            .collect();
+        if own.is_empty() {
+            return Ok(None);
+        }
+
+        let cars = self.or_unavailable("merge trains", self.get_merge_trains(project_path).await);
+        let Some((train, position)) = train_position(&cars, &own) else {
+            return Ok(None);
+        };
+        let car = train[position];
+        let iid = car.merge_request.iid;
+        let reason =
+            BlockingReason::MergeTrain(iid, car.target_branch.clone(), position + 1, train.len());
+
+        let answer = self.merge_train_answers.lock().unwrap().get(&iid).copied();
+        let go_ahead = match answer {
+            Some(go_ahead) => go_ahead,
+            None => {
+                println!(
+                    "{} {}: {}",
+                    "🚆".bright_blue(),
+                    branch,
+                    self.display_blocking_reason(&reason)
+                );
+                let running = train
+                    .iter()
+                    .filter_map(|other| other.pipeline.as_ref())
+                    .filter(|pipeline| {
+                        self.pipeline_policy(&pipeline.status) != StatusPolicy::Ignore
+                    });
+                for pipeline in running {
+                    println!(
+                        "    {}: {}",
+                        self.display_pipeline(pipeline, false),
+                        pipeline.status
+                    );
+                }
+
+                let go_ahead = self.merge_train == MergeTrainAction::Confirm
+                    && confirm_off_runtime(format!("Push anyway and take !{} off the train?", iid))
+                        .await;
+                self.merge_train_answers
+                    .lock()
+                    .unwrap()
+                    .insert(iid, go_ahead);
+                go_ahead
+            }
+        };
+
+        Ok((!go_ahead).then(|| (car.pipeline.clone(), reason)))
+    }
+
+    /// Running deployments to a blocking environment, whatever branch
+    /// triggered them.
+    async fn check_deploying_environments(
+        &self,
+        project_path: &str,
+    ) -> Result<Vec<(Pipeline, BlockingReason)>, Box<dyn std::error::Error>> {
+        let mut blocking = Vec::new();
+        if self.blocking_environments.is_empty() {
+            return Ok(blocking);
+        }
+
+        let environments = self.get_environments(project_path).await?;
+        let watched = environments.iter().filter(|env| {
+            self.blocking_environments
+                .iter()
+                .any(|pattern| pattern.matches(&env.name))
+        });
+
+        for environment in watched {
+            let deployments = self
+                .get_running_deployments(project_path, &environment.name)
+                .await?;
+            for deployment in deployments {
+                let Some(deployable) = deployment.deployable else {
+                    continue;
+                };
+                let pipeline = deployable.pipeline;
+                blocking.push((
+                    Pipeline {
+                        id: pipeline.id,
+                        status: pipeline.status,
+                        ref_name: Some(pipeline.ref_name.clone()),
+                        source: None,
+                    },
+                    BlockingReason::EnvironmentDeploying(
+                        deployment.environment.name,
+                        pipeline.ref_name,
+                    ),
+                ));
+            }
+        }
+
+        Ok(blocking)
+    }
+
+    /// Running pipelines of a watch target, on any ref matching its
+    /// pattern except the pushed ones, which are checked already.
+    async fn check_watch_target(
+        &self,
+        project: &str,
+        ref_pattern: &Pattern,
+        pushed_refs: &[String],
+    ) -> Result<Vec<(Pipeline, BlockingReason)>, Box<dyn std::error::Error>> {
+        let mut blocking_pipelines = Vec::new();
+
+        for pipeline in self
+            .get_project_pipelines(project, ref_pattern.literal())
+            .await?
+        {
+            let Some(branch) = pipeline.ref_name.clone() else {
+                continue;
+            };
+            if !ref_pattern.matches(&branch) || pushed_refs.contains(&branch) {
+                continue;
+            }
+
+            if let Some(reason) = self
+                .check_pipeline_blocking(project, &branch, &pipeline)
+                .await?
+            {
+                blocking_pipelines.push((pipeline, reason));
+            }
+        }
+
+        Ok(blocking_pipelines)
+    }
+
+    /// End of the deploy freeze in effect, if any. With overlapping
+    /// freezes, the last one to end.
+    async fn check_deploy_freeze(
+        &self,
+        project_path: &str,
+    ) -> Result<Option<DateTime<Utc>>, Box<dyn std::error::Error>> {
+        if self.freeze_branches.is_empty() || self.freeze_action == Action::Allow {
+            return Ok(None);
+        }
+
         let now = Utc::now();
-
-        if let Some(started) = started_at {
-            if let Some(start_time) = self.parse_datetime(started) {
-                return Some((now - start_time).num_seconds() as u64);
+        let mut freeze_end = None;
+        for period in self.get_freeze_periods(project_path).await? {
+            match period.active_until(now) {
+                Ok(end) => freeze_end = freeze_end.max(end),
+                Err(e) => println!("{} Ignoring freeze period: {}", "⚠️".yellow(), e),
             }
         }
 
-        if let Some(created_time) = self.parse_datetime(created_at) {
-            Some((now - created_time).num_seconds() as u64)
-        } else {
-            None
-        }
+        Ok(freeze_end)
     }
 
-    fn get_stage_order(&self, jobs: &[Job]) -> Vec<String> {
-        let mut stages = Vec::new();
-        let mut stage_set = std::collections::HashSet::new();
+    /// The latest finished pipeline on the branch if it failed, and the
+    /// failed jobs that matter. With `failed_branch_jobs`, only failures of
+    /// matching jobs count.
+    async fn check_branch_failed(
+        &self,
+        project_path: &str,
+        branch: &str,
+    ) -> Result<Option<(Pipeline, BlockingReason)>, Box<dyn std::error::Error>> {
+        if self.failed_branch_action == Action::Allow || self.fix {
+            return Ok(None);
+        }
 
-        for job in jobs {
-            if stage_set.insert(job.stage.clone()) {
-                stages.push(job.stage.clone());
+        // The branch's running pipelines still decide if this can't be read
+        let Some(pipeline) = self.or_unavailable(
+            "failed pipelines",
+            self.get_latest_finished_pipeline(project_path, branch)
+                .await,
+        ) else {
+            return Ok(None);
+        };
+        if pipeline.status != Status::Failed {
+            return Ok(None);
+        }
+
+        let mut failed_jobs = Vec::new();
+        if !self.failed_branch_jobs.is_empty() {
+            let jobs = self.or_unavailable(
+                "failed pipelines",
+                self.get_pipeline_jobs(project_path, pipeline.id).await,
+            );
+            failed_jobs = self.counted_failed_jobs(&jobs);
+            if failed_jobs.is_empty() {
+                return Ok(None);
             }
         }
 
-        stages
+        Ok(Some((pipeline, BlockingReason::BranchFailed(failed_jobs))))
     }
 
-    fn find_stage_index(&self, stages: &[String], target_stage: &str) -> Option<usize> {
-        stages.iter().position(|s| s == target_stage)
+    /// Failed jobs matching `failed_branch_jobs`, except those allowed to fail.
+    fn counted_failed_jobs(&self, jobs: &[Job]) -> Vec<String> {
+        jobs.iter()
+            .filter(|job| job.status == Status::Failed && !job.allow_failure)
+            .filter(|job| {
+                self.failed_branch_jobs.iter().any(|pattern| {
+                    let environment = job.environment.as_ref().map(|env| env.name.as_str());
+                    pattern.matches(&job.name, &job.tag_list, environment)
+                })
+            })
+            .map(|job| job.name.clone())
+            .collect()
     }
 
-    async fn check_pipeline_blocking(
+    async fn check_blocking_refs(
         &self,
         project_path: &str,
-        pipeline: &Pipeline,
-    ) -> Result<Option<BlockingReason>, Box<dyn std::error::Error>> {
-        if self.simple_mode {
-            return Ok(Some(BlockingReason::SimpleMode));
-        }
-
-        let jobs = self.get_pipeline_jobs(project_path, pipeline.id).await?;
-        let stages = self.get_stage_order(&jobs);
+        refs: &[String],
+    ) -> Result<Vec<RefVerdict>, Box<dyn std::error::Error>> {
+        let mut verdicts = Vec::new();
+        // A deployment blocks every ref of the push
+        let deploying = self.or_unavailable(
+            "deployments",
+            self.check_deploying_environments(project_path).await,
+        );
+        let freeze_end = self.or_unavailable(
+            "deploy freeze periods",
+            self.check_deploy_freeze(project_path).await,
+        );
 
-        // Check specific jobs blocking
-        if !self.blocking_jobs.is_empty() {
-            for job in &jobs {
-                if self.blocking_jobs.contains(&job.name) {
-                    match job.status.as_str() {
-                        "running" | "pending" => {
-                            return Ok(Some(BlockingReason::BlockingJobRunning(job.name.clone())));
-                        }
-                        _ => {}
-                    }
+        for branch in refs {
+            let mut blocking: Vec<_> = self
+                .check_blocking_pipelines(project_path, branch)
+                .await?
+                .into_iter()
+                .chain(
+                    self.check_merge_request_pipelines(project_path, branch)
+                        .await?,
+                )
+                .chain(
+                    self.check_merge_train_pipelines(project_path, branch)
+                        .await?,
+                )
+                .chain(deploying.iter().cloned())
+                .map(|(pipeline, reason)| (Some(pipeline), reason))
+                .collect();
+
+            let frozen = self
+                .freeze_branches
+                .iter()
+                .any(|pattern| pattern.matches(branch));
+            if let (Some(end), true) = (freeze_end, frozen) {
+                if self.freeze_action == Action::Block {
+                    blocking.push((None, BlockingReason::DeployFreeze(end)));
+                } else {
+                    println!(
+                        "{} {}: {}",
+                        "⚠️".yellow(),
+                        branch,
+                        self.display_blocking_reason(&BlockingReason::DeployFreeze(end))
+                    );
                 }
             }
-        }
 
-        // Check stage-based blocking
-        if let Some(blocking_stage) = &self.blocking_stage {
-            let blocking_stage_idx = self.find_stage_index(&stages, blocking_stage);
+            if let Some((pipeline, reason)) = self.check_branch_failed(project_path, branch).await?
+            {
+                if self.failed_branch_action == Action::Block {
+                    blocking.push((Some(pipeline), reason));
+                } else {
+                    println!(
+                        "{} {}: Pipeline #{}: {}",
+                        "⚠️".yellow(),
+                        branch,
+                        pipeline.id,
+                        self.display_blocking_reason(&reason)
+                    );
+                }
+            }
 
-            for job in &jobs {
-                match job.status.as_str() {
-                    "running" | "pending" => {
-                        // Check if we're in the blocking stage
-                        if job.stage == *blocking_stage {
-                            return Ok(Some(BlockingReason::BlockingStageRunning(
-                                job.stage.clone(),
-                            )));
-                        }
+            if let Some(blocked) = self.check_merge_train(project_path, branch).await? {
+                blocking.push(blocked);
+            }
 
-                        // Check pre-blocking logic
-                        if let Some(blocking_idx) = blocking_stage_idx {
-                            let current_stage_idx = self.find_stage_index(&stages, &job.stage);
-
-                            if let Some(current_idx) = current_stage_idx {
-                                // We're in stage -1 of blocking stage
-                                if current_idx == blocking_idx.saturating_sub(1) {
-                                    if let Some(seconds_running) = self.seconds_since_start(
-                                        job.started_at.as_ref(),
-                                        &job.created_at,
-                                    ) {
-                                        if seconds_running >= self.pre_block_duration {
-                                            return Ok(Some(BlockingReason::PreBlockingStage(
-                                                job.stage.clone(),
-                                                seconds_running,
-                                            )));
-                                        }
-                                    }
-                                }
-
-                                // We're in stage +1 of blocking stage, check post-block timing
-                                if current_idx == blocking_idx + 1 {
-                                    if let Some(seconds_running) = self.seconds_since_start(
-                                        job.started_at.as_ref(),
-                                        &job.created_at,
-                                    ) {
-                                        if seconds_running < self.post_block_duration {
-                                            return Ok(Some(BlockingReason::BlockingStageRunning(
-                                                format!("{} (post-block)", job.stage),
-                                            )));
-                                        }
-                                    }
-                                }
-                            }
-                        }
-                    }
-                    _ => {}
+            for window in self.blackouts.iter().filter(|w| w.applies_to(branch)) {
+                if let Some(end) = window.active_until(Utc::now()) {
+                    blocking.push((None, BlockingReason::Blackout(window.label.clone(), end)));
                 }
             }
+
+            verdicts.push(RefVerdict {
+                r#ref: branch.clone(),
+                watched: false,
+                blocking,
+            });
         }
 
-        Ok(None)
-    }
+        for (project, ref_pattern) in &self.watch {
+            let project = project.as_deref().unwrap_or(project_path);
+            // Pushed refs of the pushed project are checked above
+            let skipped: &[String] = if project == project_path { refs } else { &[] };
+            let target = format!("{} @ {}", project, ref_pattern);
+            // A target that can't be read must not take the pushed refs down with it
+            let blocking = self.or_unavailable(
+                &format!("watch target {}", target),
+                self.check_watch_target(project, ref_pattern, skipped).await,
+            );
+            verdicts.push(RefVerdict {
+                r#ref: target,
+                watched: true,
+                blocking: blocking
+                    .into_iter()
+                    .map(|(pipeline, reason)| (Some(pipeline), reason))
+                    .collect(),
+            });
+        }
 
-    async fn check_blocking_pipelines(
-        &self,
-        project_path: &str,
-        branch: &str,
-    ) -> Result<Vec<(Pipeline, BlockingReason)>, Box<dyn std::error::Error>> {
-        let pipelines = self.get_project_pipelines(project_path, branch).await?;
-        let mut blocking_pipelines = Vec::new();
+        Ok(verdicts)
+    }
 
-        let running_statuses = ["running", "pending", "created"];
+    fn display_verdicts(&self, verdicts: &[RefVerdict]) {
+        for verdict in verdicts {
+            let label = if verdict.watched {
+                format!("watched {}", verdict.r#ref)
+            } else {
+                verdict.r#ref.clone()
+            };
+            if verdict.blocking.is_empty() {
+                println!("  {} {}: clear", "✅".green(), label);
+                continue;
+            }
 
-        for pipeline in pipelines {
-            if running_statuses.contains(&pipeline.status.as_str()) {
-                if let Some(reason) = self
-                    .check_pipeline_blocking(project_path, &pipeline)
-                    .await?
-                {
-                    blocking_pipelines.push((pipeline, reason));
+            println!("  {} {}:", "❌".red(), label.bright_white());
+            for (pipeline, reason) in &verdict.blocking {
+                match pipeline {
+                    Some(pipeline) => println!(
+                        "    {}: {}",
+                        self.display_pipeline(pipeline, verdict.watched),
+                        self.display_blocking_reason(reason)
+                    ),
+                    None => println!("    {}", self.display_blocking_reason(reason)),
                 }
             }
         }
+    }
 
-        Ok(blocking_pipelines)
+    /// `Pipeline #N`, with its ref for watched pipelines, which may be on
+    /// any ref matching the target.
+    fn display_pipeline(&self, pipeline: &Pipeline, watched: bool) -> String {
+        // refs/merge-requests/:iid/head, /merge (merged results) or /train
+        let merge_request = pipeline
+            .ref_name
+            .as_deref()
+            .and_then(|r#ref| r#ref.strip_prefix("refs/merge-requests/")?.split_once('/'));
+        match merge_request {
+            Some((iid, "train")) => {
+                return format!("Merge train pipeline #{} (!{})", pipeline.id, iid)
+            }
+            Some((iid, _)) => return format!("Pipeline #{} (!{})", pipeline.id, iid),
+            None => {}
+        }
+
+        match &pipeline.ref_name {
+            Some(branch) if watched => format!("Pipeline #{} on '{}'", pipeline.id, branch),
+            _ => format!("Pipeline #{}", pipeline.id),
+        }
     }
 
     fn display_blocking_reason(&self, reason: &BlockingReason) -> String {
         match reason {
             BlockingReason::SimpleMode => "Pipeline running (simple mode)".to_string(),
-            BlockingReason::BlockingStageRunning(stage) => {
+            BlockingReason::Rule(rule, detail) => format!("{} [{}]", detail, rule),
+            BlockingReason::BlockingStageRunning(stage, _) => {
                 format!("Blocking stage '{}' is running", stage)
             }
-            BlockingReason::BlockingJobRunning(job) => format!("Blocking job '{}' is running", job),
+            BlockingReason::BlockingJobRunning(job, pattern, _) if job == pattern => {
+                format!("Blocking job '{}' is running", job)
+            }
+            BlockingReason::BlockingJobRunning(job, pattern, _) => {
+                format!("Blocking job '{}' is running (matches '{}')", job, pattern)
+            }
             BlockingReason::PreBlockingStage(stage, seconds) => {
                 format!(
                     "Stage '{}' running for {}s (approaching blocking stage)",
                     stage, seconds
                 )
             }
+            BlockingReason::PostBlockingStage(stage, _) => {
+                format!("Blocking stage '{} (post-block)' is running", stage)
+            }
+            BlockingReason::PrerequisiteRunning(job, blocking_job, seconds) => {
+                format!(
+                    "Job '{}' running for {}s, needed by blocking job '{}'",
+                    job, seconds, blocking_job
+                )
+            }
+            BlockingReason::Downstream(path, _, _, reason) => {
+                format!("{} → {}", path, self.display_blocking_reason(reason))
+            }
+            BlockingReason::EnvironmentDeploying(environment, branch) => {
+                format!("Deploying to '{}' from '{}'", environment, branch)
+            }
+            BlockingReason::DeployFreeze(end) => format!(
+                "Deploy freeze until {}",
+                end.with_timezone(&Local).format("%a %Y-%m-%d %H:%M %:z")
+            ),
+            BlockingReason::Blackout(window, Some(end)) => format!(
+                "Blackout '{}' until {}",
+                window,
+                end.with_timezone(&Local).format("%a %Y-%m-%d %H:%M %:z")
+            ),
+            BlockingReason::Blackout(window, None) => {
+                format!("Blackout '{}' with no end in sight", window)
+            }
+            BlockingReason::BranchFailed(jobs) if jobs.is_empty() => {
+                "Latest pipeline failed".to_string()
+            }
+            BlockingReason::BranchFailed(jobs) => {
+                format!("Latest pipeline failed in {}", jobs.join(", "))
+            }
+            BlockingReason::ManualJobWaiting(job) => {
+                format!("Manual job '{}' is waiting to be played", job)
+            }
+            BlockingReason::MergeTrain(iid, target, position, length) => {
+                format!(
+                    "!{} is on the merge train into '{}' (position {} of {})",
+                    iid, target, position, length
+                )
+            }
+        }
+    }
+
+    /// Whether waiting will eventually clear the reason. Freezes can last
+    /// a weekend, so they are only waited for on request.
+    fn can_wait_out(&self, reason: &BlockingReason) -> bool {
+        match reason {
+            BlockingReason::DeployFreeze(_) => self.wait_for_freeze,
+            BlockingReason::Blackout(_, end) => end.is_some(),
+            BlockingReason::BranchFailed(_) => false,
+            BlockingReason::Downstream(_, _, _, reason) => self.can_wait_out(reason),
+            _ => true,
+        }
+    }
+
+    /// How to get past the reasons that can't be waited out.
+    fn display_hints(&self, verdicts: &[RefVerdict]) {
+        let mut hints = Vec::new();
+        for (_, reason) in verdicts.iter().flat_map(|verdict| &verdict.blocking) {
+            let hint = match reason {
+                BlockingReason::DeployFreeze(_) if !self.wait_for_freeze => {
+                    "Use --wait-for-freeze to wait until the freeze ends"
+                }
+                BlockingReason::BranchFailed(_) => {
+                    "Fix the branch first, or use --fix if this push is the fix"
+                }
+                _ => continue,
+            };
+            if !hints.contains(&hint) {
+                hints.push(hint);
+            }
+        }
+
+        for hint in hints {
+            println!("{} {}", "💡".bright_blue(), hint);
+        }
+    }
+
+    /// When the reason goes away by itself, if that is known in advance.
+    fn clears_at(&self, reason: &BlockingReason) -> Option<DateTime<Utc>> {
+        match reason {
+            BlockingReason::DeployFreeze(end) => Some(*end),
+            BlockingReason::Blackout(_, end) => *end,
+            BlockingReason::PostBlockingStage(_, end) => Some(*end),
+            BlockingReason::Downstream(_, _, _, reason) => self.clears_at(reason),
+            _ => None,
         }
     }
 
+    /// Poll until nothing blocks, a blocking condition that can't be
+    /// waited out shows up, or the timeout expires, even in the middle of
+    /// a GitLab request. `verdicts` are those of the first check.
     async fn wait_for_pipeline(
         &self,
         project_path: &str,
-        branch: &str,
-    ) -> Result<(), Box<dyn std::error::Error>> {
+        refs: &[String],
+        verdicts: Vec<RefVerdict>,
+        deadline: Option<Instant>,
+    ) -> Result<CheckOutcome, Box<dyn std::error::Error>> {
         println!("{} Blocking condition detected. Waiting...", "⏳".yellow());
 
+        let mut last = verdicts;
+        let polling = self.poll_until_clear(project_path, refs, &mut last);
+        let Some(deadline) = deadline else {
+            return polling.await;
+        };
+        match timeout_at(deadline, polling).await {
+            Ok(outcome) => outcome,
+            Err(_) => Ok(self.timed_out(&last)),
+        }
+    }
+
+    /// Report the timeout with what was still blocking, nothing if GitLab
+    /// didn't answer the first check.
+    fn timed_out(&self, verdicts: &[RefVerdict]) -> CheckOutcome {
+        let timeout = humantime::format_duration(self.timeout.unwrap_or_default());
+        if verdicts.is_empty() {
+            println!(
+                "{} Timed out after {} waiting for GitLab",
+                "⌛".red(),
+                timeout
+            );
+        } else {
+            println!(
+                "{} Timed out after {}, still blocking:",
+                "⌛".red(),
+                timeout
+            );
+            self.display_verdicts(verdicts);
+        }
+        CheckOutcome::TimedOut
+    }
+
+    /// The polling of `wait_for_pipeline`, keeping the verdicts of the
+    /// last check in `last`.
+    async fn poll_until_clear(
+        &self,
+        project_path: &str,
+        refs: &[String],
+        last: &mut Vec<RefVerdict>,
+    ) -> Result<CheckOutcome, Box<dyn std::error::Error>> {
         loop {
-            let blocking = self.check_blocking_pipelines(project_path, branch).await?;
+            let verdicts = self.check_blocking_refs(project_path, refs).await?;
+            let blocked: Vec<&RefVerdict> = verdicts
+                .iter()
+                .filter(|verdict| !verdict.blocking.is_empty())
+                .collect();
 
-            if blocking.is_empty() {
+            if blocked.is_empty() {
                 println!(
                     "{} No more blocking conditions, push authorized!",
                     "✅".green()
                 );
-                return Ok(());
+                return Ok(CheckOutcome::Authorized);
             }
 
-            if let Some((pipeline, reason)) = blocking.first() {
+            let reasons = blocked.iter().flat_map(|verdict| &verdict.blocking);
+            if reasons
+                .clone()
+                .any(|(_, reason)| !self.can_wait_out(reason))
+            {
                 println!(
-                    "{} Pipeline #{} - {}",
-                    "⏳".yellow(),
-                    pipeline.id,
-                    self.display_blocking_reason(reason).bright_cyan()
+                    "{} Blocking condition can't be waited out, push cancelled:",
+                    "❌".red()
                 );
-                println!("   Next check in {} seconds...", self.check_interval);
+                self.display_verdicts(&verdicts);
+                self.display_hints(&verdicts);
+                return Ok(CheckOutcome::Cancelled);
+            }
+
+            let mut eta = None;
+            for verdict in &blocked {
+                for (idx, (pipeline, reason)) in verdict.blocking.iter().enumerate() {
+                    // Watch targets may be in another project
+                    let estimate = match pipeline {
+                        Some(pipeline) if !verdict.watched => {
+                            let r#ref = pipeline.ref_name.as_deref().unwrap_or(&verdict.r#ref);
+                            self.estimate_clear(project_path, r#ref, reason).await
+                        }
+                        _ => None,
+                    };
+                    eta = eta.max(estimate);
+                    if idx > 0 {
+                        continue;
+                    }
+
+                    let pipeline = pipeline
+                        .as_ref()
+                        .map(|pipeline| {
+                            format!(" - {}", self.display_pipeline(pipeline, verdict.watched))
+                        })
+                        .unwrap_or_default();
+                    let estimate = estimate
+                        .map(|at| format!(" (ETA {})", at.with_timezone(&Local).format("%H:%M:%S")))
+                        .unwrap_or_default();
+                    println!(
+                        "{} {}{} - {}{}",
+                        "⏳".yellow(),
+                        verdict.r#ref,
+                        pipeline,
+                        self.display_blocking_reason(reason).bright_cyan(),
+                        estimate
+                    );
+                }
+            }
+
+            // Nothing can be pushed before the last timed condition clears
+            let clears_at = reasons
+                .filter_map(|(_, reason)| self.clears_at(reason))
+                .max();
+            let now = Utc::now();
+            let until = |at: DateTime<Utc>| (at - now).num_seconds().max(0) as u64;
+            let delay = self.polling.next_delay(
+                clears_at.map(until),
+                eta.map(until),
+                fastrand::f64() * 2.0 - 1.0,
+            );
+            match clears_at {
+                Some(clears_at) if delay > until(clears_at) => println!(
+                    "   Next check at {}...",
+                    (now + chrono::Duration::seconds(delay as i64))
+                        .with_timezone(&Local)
+                        .format("%Y-%m-%d %H:%M:%S")
+                ),
+                _ => println!("   Next check in {} seconds...", delay),
             }
 
-            sleep(Duration::from_secs(self.check_interval)).await;
+            *last = verdicts;
+            sleep(Duration::from_secs(delay)).await;
         }
     }
 
@@ -499,7 +2390,11 @@ impl GitLabSafePush {
             cmd_args.join(" ")
         );
 
-        let status = Command::new("git").args(&cmd_args).status()?;
+        // The pre-push hook, if installed, doesn't need to check again
+        let status = Command::new("git")
+            .args(&cmd_args)
+            .env(hook::CHECKED_ENV, "1")
+            .status()?;
 
         if status.success() {
             println!("{} Push completed successfully!", "✅".green());
@@ -512,82 +2407,222 @@ impl GitLabSafePush {
 
     fn display_config(&self) {
         println!("{} Configuration:", "⚙️".bright_blue());
+        if let Some(profile) = &self.profile {
+            println!("  Profile: {}", profile.bright_white());
+        }
         if self.simple_mode {
             println!(
                 "  Mode: {} (block on any running pipeline)",
                 "Simple".bright_yellow()
             );
+        } else if !self.rules.is_empty() {
+            println!(
+                "  Mode: {} (first matching rule applies)",
+                "Rules".bright_green()
+            );
+            for (idx, rule) in self.rules.summary().iter().enumerate() {
+                println!("  {}. {}", idx + 1, rule.bright_white());
+            }
         } else {
             println!("  Mode: {}", "Advanced".bright_green());
             if let Some(stage) = &self.blocking_stage {
-                println!("  Blocking stage: {}", stage.bright_white());
+                println!("  Blocking stage: {}", stage.to_string().bright_white());
                 println!("  Pre-block duration: {}s", self.pre_block_duration);
                 println!("  Post-block duration: {}s", self.post_block_duration);
             }
             if !self.blocking_jobs.is_empty() {
                 println!(
                     "  Blocking jobs: {}",
-                    self.blocking_jobs.join(", ").bright_white()
+                    self.blocking_jobs
+                        .iter()
+                        .map(|pattern| pattern.to_string())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                        .bright_white()
                 );
             }
         }
-        println!("  Check interval: {}s", self.check_interval);
+        if !self.blocking_environments.is_empty() {
+            println!(
+                "  Blocking environments: {}",
+                self.blocking_environments
+                    .iter()
+                    .map(|pattern| pattern.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+                    .bright_white()
+            );
+        }
+        if !self.freeze_branches.is_empty() {
+            let branches: Vec<String> = self
+                .freeze_branches
+                .iter()
+                .map(|pattern| pattern.to_string())
+                .collect();
+            println!(
+                "  Deploy freezes: {} ({})",
+                branches.join(", ").bright_white(),
+                self.freeze_action
+            );
+        }
+        if self.manual_jobs != Action::Allow && !self.simple_mode && self.rules.is_empty() {
+            println!("  Manual blocking jobs: {}", self.manual_jobs);
+        }
+        if self.failed_branch_action != Action::Allow {
+            let scope = if self.failed_branch_jobs.is_empty() {
+                "any job".to_string()
+            } else {
+                self.failed_branch_jobs
+                    .iter()
+                    .map(|pattern| pattern.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+            println!(
+                "  Failed branch check: {} ({}){}",
+                self.failed_branch_action,
+                scope.bright_white(),
+                if self.fix { ", skipped by --fix" } else { "" }
+            );
+        }
+        if !self.merge_request_pipelines {
+            println!("  Merge request pipelines: {}", "ignored".bright_white());
+        }
+        if self.merge_train_pipelines {
+            println!("  Merge train pipelines: {}", "checked".bright_white());
+        }
+        if self.merge_train != MergeTrainAction::Allow {
+            println!("  Branch on a merge train: {}", self.merge_train);
+        }
+        if !self.blackouts.is_empty() {
+            let labels: Vec<&str> = self.blackouts.iter().map(|w| w.label.as_str()).collect();
+            println!("  Blackouts: {}", labels.join(", ").bright_white());
+        }
+        if !self.watch.is_empty() {
+            let targets: Vec<String> = self
+                .watch
+                .iter()
+                .map(|(project, ref_pattern)| match project {
+                    Some(project) => format!("{} @ {}", project, ref_pattern),
+                    None => ref_pattern.to_string(),
+                })
+                .collect();
+            println!("  Watching: {}", targets.join(", ").bright_white());
+        }
+        if !self.pipeline_statuses.is_empty() || !self.job_statuses.is_empty() {
+            let checked: Vec<String> = self
+                .checked_pipeline_statuses()
+                .iter()
+                .map(|status| match self.pipeline_policy(status) {
+                    StatusPolicy::Check => format!("{} (check)", status),
+                    _ => status.to_string(),
+                })
+                .collect();
+            let active: Vec<&str> = Status::KNOWN
+                .iter()
+                .chain(
+                    self.job_statuses
+                        .keys()
+                        .filter(|status| matches!(status, Status::Unknown(_))),
+                )
+                .filter(|status| self.job_active(status))
+                .map(|status| status.as_str())
+                .collect();
+            println!("  Pipeline statuses: {}", checked.join(", ").bright_white());
+            println!(
+                "  Running job statuses: {}",
+                active.join(", ").bright_white()
+            );
+        }
+        println!(
+            "  Check interval: {}s (adaptive, {}s to {}s)",
+            self.polling.interval, self.polling.min, self.polling.max
+        );
+        if let Some(timeout) = self.timeout {
+            println!("  Timeout: {}", humantime::format_duration(timeout));
+        }
         println!();
     }
 
-    async fn safe_push(
+    /// Check the refs about to be pushed, waiting if allowed.
+    /// Returns whether the push may proceed.
+    async fn check_push(
         &self,
-        git_args: &[String],
+        target: &PushTarget,
+        remote: RemoteUrl,
         wait: bool,
-    ) -> Result<bool, Box<dyn std::error::Error>> {
-        let branch = self.get_current_branch()?;
-        let remote_url = self.get_remote_url()?;
-
-        let project_path = self
-            .parse_gitlab_project(&remote_url)
-            .ok_or("Unable to parse GitLab URL from git remote")?;
+    ) -> Result<CheckOutcome, Box<dyn std::error::Error>> {
+        let project_path = remote.with_instance_prefix(&self.instance_prefix()).path;
+        let refs = &target.refs;
 
         println!(
             "{} Project: {}",
             "📋".bright_blue(),
             project_path.bright_white()
         );
-        println!("{} Branch: {}", "🌿".bright_green(), branch.bright_white());
+        println!(
+            "{} Remote: {}",
+            "🔗".bright_blue(),
+            target.remote.bright_white()
+        );
+        println!(
+            "{} Branch: {}",
+            "🌿".bright_green(),
+            refs.join(", ").bright_white()
+        );
         self.display_config();
 
-        match self.check_blocking_pipelines(&project_path, &branch).await {
-            Ok(blocking_pipelines) => {
-                if blocking_pipelines.is_empty() {
+        // The timeout covers the first check too, waiting or not, so a
+        // stalled GitLab can't hang a script or hook
+        let deadline = self.timeout.map(|timeout| Instant::now() + timeout);
+        let check = self.check_blocking_refs(&project_path, refs);
+        let checked = match deadline {
+            Some(deadline) => match timeout_at(deadline, check).await {
+                Ok(checked) => checked,
+                Err(_) => return Ok(self.timed_out(&[])),
+            },
+            None => check.await,
+        };
+
+        match checked {
+            Ok(verdicts) => {
+                if verdicts.iter().all(|verdict| verdict.blocking.is_empty()) {
                     println!(
                         "{} No blocking conditions detected, push authorized!",
                         "✅".green()
                     );
-                    return Ok(self.do_push(git_args)?);
+                    return Ok(CheckOutcome::Authorized);
                 }
 
-                if !wait {
+                let stuck = verdicts
+                    .iter()
+                    .flat_map(|verdict| &verdict.blocking)
+                    .any(|(_, reason)| !self.can_wait_out(reason));
+
+                if !wait || stuck {
                     println!(
                         "{} Blocking condition detected, push cancelled:",
                         "❌".red()
                     );
-                    for (pipeline, reason) in &blocking_pipelines {
+                    self.display_verdicts(&verdicts);
+                    if stuck {
+                        self.display_hints(&verdicts);
+                    } else {
                         println!(
-                            "  Pipeline #{}: {}",
-                            pipeline.id,
-                            self.display_blocking_reason(reason)
+                            "{} Use gitlab-safe-push --wait to wait for completion",
+                            "💡".bright_blue()
                         );
                     }
-                    println!("{} Use --wait to wait for completion", "💡".bright_blue());
-                    return Ok(false);
+                    return Ok(CheckOutcome::Cancelled);
                 }
 
-                self.wait_for_pipeline(&project_path, &branch).await?;
-                Ok(self.do_push(git_args)?)
+                self.wait_for_pipeline(&project_path, refs, verdicts, deadline)
+                    .await
             }
             Err(e) => {
                 println!("{} Unable to check pipelines: {}", "⚠️".yellow(), e);
                 println!("{} Push authorized with warning", "⚠️".yellow());
-                Ok(self.do_push(git_args)?)
+                Ok(CheckOutcome::Authorized)
             }
         }
     }
@@ -595,31 +2630,622 @@ impl GitLabSafePush {
 
 #[tokio::main]
 async fn main() -> ExitCode {
-    let cli = Cli::parse();
-
-    let wait = !cli.no_wait;
-
-    match GitLabSafePush::new(
-        cli.gitlab_url,
-        cli.token,
-        cli.blocking_stage,
-        cli.blocking_jobs,
-        cli.pre_block_duration,
-        cli.post_block_duration,
-        cli.check_interval,
-        cli.simple_mode,
-    ) {
-        Ok(safe_push) => match safe_push.safe_push(&cli.git_args, wait).await {
-            Ok(true) => ExitCode::SUCCESS,
-            Ok(false) => ExitCode::FAILURE,
-            Err(e) => {
-                eprintln!("{} Error: {}", "❌".red(), e);
-                ExitCode::FAILURE
+    let matches = Cli::command().get_matches();
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+
+    let hook_run = match &cli.command {
+        Some(Commands::Hook {
+            action: HookAction::Install { force },
+        }) => {
+            return match hook::install(*force) {
+                Ok(path) => {
+                    println!(
+                        "{} Pre-push hook installed: {}",
+                        "✅".green(),
+                        path.display()
+                    );
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("{} Error: {}", "❌".red(), e);
+                    ExitCode::FAILURE
+                }
+            };
+        }
+        Some(Commands::Hook {
+            action: HookAction::Run { remote, url },
+        }) => Some((remote, url)),
+        None => None,
+    };
+    // A hook vetoes: a blocked `git push` must not hang an IDE or terminal
+    let wait = hook_run.is_none() && !cli.no_wait;
+
+    // The remote decides which profile applies, so resolve it first.
+    // In hook mode git already knows what it pushes, we only veto.
+    let target = match hook_run {
+        Some((remote, url)) => {
+            if env::var_os(hook::CHECKED_ENV).is_some() {
+                return ExitCode::SUCCESS;
             }
-        },
+            hook::read_pushed_refs().map(|refs| PushTarget {
+                remote: remote.clone(),
+                remote_url: url.clone(),
+                refs,
+            })
+        }
+        None => {
+            if let Some(option) = misplaced_option(&cli.git_args) {
+                eprintln!(
+                    "{} Error: {} comes after the git arguments and would be passed to git push; put gitlab-safe-push options first",
+                    "❌".red(),
+                    option
+                );
+                return ExitCode::FAILURE;
+            }
+            PushTarget::resolve(&cli.git_args)
+        }
+    };
+    let target = match target {
+        Ok(target) if hook_run.is_some() && target.refs.is_empty() => return ExitCode::SUCCESS,
+        Ok(target) => target,
+        Err(e) => {
+            eprintln!("{} Error: {}", "❌".red(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let Some(remote) = RemoteUrl::parse(&target.remote_url) else {
+        // The hook runs for every remote, not only GitLab ones
+        if hook_run.is_some() {
+            println!(
+                "{} {} is not a GitLab remote, push not checked",
+                "💡".bright_blue(),
+                target.remote
+            );
+            return ExitCode::SUCCESS;
+        }
+        eprintln!(
+            "{} Error: Unable to parse GitLab URL from git remote",
+            "❌".red()
+        );
+        return ExitCode::FAILURE;
+    };
+
+    let (cli_config, env_config) = cli.config(&matches);
+    let config = match Config::load(cli_config, env_config, &remote) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{} Configuration error: {}", "❌".red(), e);
+            eprintln!(
+                "{} Push refused until the configuration is fixed",
+                "❌".red()
+            );
+            return ExitCode::FAILURE;
+        }
+    };
+
+    // Other hosts, such as GitHub, are pushed to unchecked by the hook
+    if hook_run.is_some() && !on_instance(&config, &remote) {
+        println!(
+            "{} {} is not on the configured GitLab instance, push not checked",
+            "💡".bright_blue(),
+            target.remote
+        );
+        return ExitCode::SUCCESS;
+    }
+
+    let mut safe_push = match GitLabSafePush::new(config) {
+        Ok(safe_push) => safe_push,
         Err(e) => {
             eprintln!("{} Configuration error: {}", "❌".red(), e);
+            return ExitCode::FAILURE;
+        }
+    };
+    safe_push.explain = cli.explain;
+    safe_push.fix = cli.fix;
+    safe_push.wait_for_freeze = cli.wait_for_freeze;
+
+    let result = match safe_push.check_push(&target, remote, wait).await {
+        Ok(CheckOutcome::TimedOut) => return ExitCode::from(TIMEOUT_EXIT_CODE),
+        Ok(CheckOutcome::Authorized) if hook_run.is_none() => safe_push.do_push(&cli.git_args),
+        Ok(outcome) => Ok(outcome == CheckOutcome::Authorized),
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(e) => {
+            eprintln!("{} Error: {}", "❌".red(), e);
             ExitCode::FAILURE
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    /// Serve canned GitLab responses, with their status, by request path,
+    /// 404 for the rest.
+    async fn serve(routes: &'static [(&'static str, &'static str, &'static str)]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![0; 8192];
+                let read = stream.read(&mut request).await.unwrap_or(0);
+                let request = String::from_utf8_lossy(&request[..read]);
+                let target = request.split_whitespace().nth(1).unwrap_or_default();
+                let path = target.split('?').next().unwrap_or_default();
+                let (status, body) = routes
+                    .iter()
+                    .find(|(route, _, _)| *route == path)
+                    .map_or(("404 Not Found", "{}"), |(_, status, body)| {
+                        (*status, *body)
+                    });
+                let response = format!(
+                    "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        url
+    }
+
+    /// GitLab of the tests that don't reach it
+    const GITLAB_URL: &str = "https://gitlab.example.com";
+
+    /// A checker for the GitLab at `gitlab_url`, with a token.
+    fn safe_push(gitlab_url: &str, config: Config) -> GitLabSafePush {
+        GitLabSafePush::new(Config {
+            token: Some("token".to_string()),
+            gitlab_url: Some(gitlab_url.to_string()),
+            ..config
+        })
+        .unwrap()
+    }
+
+    fn jobs(jobs: serde_json::Value) -> Vec<Job> {
+        serde_json::from_value(jobs).unwrap()
+    }
+
+    #[test]
+    fn counts_failed_jobs_matching_the_patterns() {
+        let safe_push = safe_push(
+            GITLAB_URL,
+            Config {
+                failed_branch: Some(Action::Block),
+                failed_branch_jobs: Some("test*, deploy".to_string()),
+                ..Config::default()
+            },
+        );
+        let jobs = jobs(serde_json::json!([
+            {"id": 1, "name": "test:unit", "stage": "test", "status": "failed", "started_at": null, "created_at": ""},
+            {"id": 2, "name": "test:flaky", "stage": "test", "status": "failed", "allow_failure": true, "started_at": null, "created_at": ""},
+            {"id": 3, "name": "lint", "stage": "test", "status": "failed", "started_at": null, "created_at": ""},
+            {"id": 4, "name": "deploy", "stage": "deploy", "status": "success", "started_at": null, "created_at": ""},
+        ]));
+
+        assert_eq!(safe_push.counted_failed_jobs(&jobs), ["test:unit"]);
+    }
+
+    #[test]
+    fn clamps_starts_ahead_of_the_clock() {
+        let safe_push = safe_push(GITLAB_URL, Config::default());
+        let ahead = (Utc::now() + chrono::Duration::seconds(30)).to_rfc3339();
+
+        assert_eq!(safe_push.seconds_since_start(Some(&ahead), ""), Some(0));
+        assert_eq!(safe_push.seconds_since_start(None, &ahead), Some(0));
+    }
+
+    #[test]
+    fn infers_stage_order_from_job_ids() {
+        let safe_push = safe_push(GITLAB_URL, Config::default());
+        let jobs = jobs(serde_json::json!([
+            {"id": 5, "name": "deploy", "stage": "deploy", "status": "created", "started_at": null, "created_at": ""},
+            {"id": 3, "name": "unit", "stage": "test", "status": "running", "started_at": null, "created_at": ""},
+            {"id": 1, "name": "compile", "stage": "build", "status": "success", "started_at": null, "created_at": ""},
+            {"id": 4, "name": "lint", "stage": "test", "status": "running", "started_at": null, "created_at": ""},
+        ]));
+
+        assert_eq!(
+            safe_push.infer_stage_order(&jobs),
+            ["build", "test", "deploy"]
+        );
+    }
+
+    #[tokio::test]
+    async fn prefers_graphql_stage_order_to_inference() {
+        // Listed by id, `test` would come before `build`
+        const JOBS: &str = r#"[
+            {"id": 1, "name": "unit", "stage": "test", "status": "running", "started_at": "2020-01-01T00:00:00Z", "created_at": ""},
+            {"id": 2, "name": "compile", "stage": "build", "status": "success", "started_at": null, "created_at": ""},
+            {"id": 3, "name": "deploy", "stage": "deploy", "status": "created", "started_at": null, "created_at": ""}
+        ]"#;
+        const GRAPH: &str = r#"{"data": {"project": {"pipeline": {"stages": {"nodes": [
+            {"name": "build", "groups": {"nodes": [{"jobs": {"nodes": [{"name": "compile", "schedulingType": "stage", "needs": {"nodes": []}}]}}]}},
+            {"name": "test", "groups": {"nodes": [{"jobs": {"nodes": [{"name": "unit", "schedulingType": "stage", "needs": {"nodes": []}}]}}]}},
+            {"name": "deploy", "groups": {"nodes": [{"jobs": {"nodes": [{"name": "deploy", "schedulingType": "stage", "needs": {"nodes": []}}]}}]}}
+        ]}}}}}"#;
+        async fn check(gitlab_url: String) -> Option<BlockingReason> {
+            let safe_push = safe_push(
+                &gitlab_url,
+                Config {
+                    blocking_stage: Some("deploy".to_string()),
+                    ..Config::default()
+                },
+            );
+            let pipeline: Pipeline = serde_json::from_value(
+                serde_json::json!({"id": 1, "status": "running", "ref": "main"}),
+            )
+            .unwrap();
+            safe_push
+                .check_pipeline_blocking("grp/proj", "main", &pipeline)
+                .await
+                .unwrap()
+        }
+
+        let inferred = serve(&[
+            (
+                "/api/v4/projects/grp%2Fproj/pipelines/1/jobs",
+                "200 OK",
+                JOBS,
+            ),
+            (
+                "/api/v4/projects/grp%2Fproj/pipelines/1/bridges",
+                "200 OK",
+                "[]",
+            ),
+        ])
+        .await;
+        assert!(check(inferred).await.is_none());
+
+        let from_graphql = serve(&[
+            (
+                "/api/v4/projects/grp%2Fproj/pipelines/1/jobs",
+                "200 OK",
+                JOBS,
+            ),
+            (
+                "/api/v4/projects/grp%2Fproj/pipelines/1/bridges",
+                "200 OK",
+                "[]",
+            ),
+            ("/api/graphql", "200 OK", GRAPH),
+        ])
+        .await;
+        assert!(matches!(
+            check(from_graphql).await,
+            Some(BlockingReason::PrerequisiteRunning(job, blocking, _))
+                if job == "unit" && blocking == "deploy"
+        ));
+    }
+
+    #[test]
+    fn tracks_manual_jobs_until_played() {
+        let safe_push = safe_push(
+            GITLAB_URL,
+            Config {
+                blocking_jobs: Some("deploy".to_string()),
+                manual_jobs: Some(Action::Block),
+                ..Config::default()
+            },
+        );
+        let deploy = |status: &str| {
+            jobs(serde_json::json!([
+                {"id": 1, "name": "build", "stage": "build", "status": "success", "started_at": null, "created_at": ""},
+                {"id": 2, "name": "deploy", "stage": "deploy", "status": status, "started_at": null, "created_at": ""},
+            ]))
+        };
+
+        let waiting = deploy("manual");
+        assert!(matches!(
+            safe_push.check_manual_jobs(1, &waiting),
+            Some(BlockingReason::ManualJobWaiting(job)) if job == "deploy"
+        ));
+        assert!(safe_push.played_jobs(&waiting).is_empty());
+
+        let played = deploy("pending");
+        assert!(safe_push.check_manual_jobs(1, &played).is_none());
+        let names: Vec<&str> = safe_push
+            .played_jobs(&played)
+            .iter()
+            .map(|job| job.name.as_str())
+            .collect();
+        assert_eq!(names, ["deploy"]);
+        // Announced once only
+        assert!(safe_push.played_jobs(&played).is_empty());
+    }
+
+    #[test]
+    fn finds_position_on_merge_train() {
+        let cars: Vec<MergeTrainCar> = serde_json::from_value(serde_json::json!([
+            {"merge_request": {"iid": 1}, "target_branch": "main", "pipeline": null},
+            {"merge_request": {"iid": 2}, "target_branch": "release", "pipeline": null},
+            {"merge_request": {"iid": 3}, "target_branch": "main", "pipeline": null},
+            {"merge_request": {"iid": 4}, "target_branch": "main", "pipeline": null},
+        ]))
+        .unwrap();
+        let position = |own: &[u64]| {
+            train_position(&cars, own).map(|(train, position)| {
+                let iids: Vec<u64> = train.iter().map(|car| car.merge_request.iid).collect();
+                (iids, position)
+            })
+        };
+
+        assert_eq!(position(&[3]), Some((vec![1, 3, 4], 1)));
+        assert_eq!(position(&[2]), Some((vec![2], 0)));
+        assert_eq!(position(&[9, 4]), Some((vec![1, 3, 4], 2)));
+        assert_eq!(position(&[9]), None);
+    }
+
+    #[tokio::test]
+    async fn unreadable_watch_target_keeps_pushed_refs_blocked() {
+        let gitlab_url = serve(&[
+            (
+                "/api/v4/projects/grp%2Fproj/pipelines",
+                "200 OK",
+                r#"[{"id": 1, "status": "running", "ref": "main"}]"#,
+            ),
+            ("/api/v4/projects/grp%2Fproj/merge_requests", "200 OK", "[]"),
+        ])
+        .await;
+        let safe_push = safe_push(
+            &gitlab_url,
+            Config {
+                watch: Some(vec![WatchTarget {
+                    project: Some("grp/missing".to_string()),
+                    ref_pattern: "main".to_string(),
+                }]),
+                ..Config::default()
+            },
+        );
+
+        let verdicts = safe_push
+            .check_blocking_refs("grp/proj", &["main".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(verdicts.len(), 2);
+        assert!(matches!(
+            verdicts[0].blocking[..],
+            [(Some(_), BlockingReason::SimpleMode)]
+        ));
+        assert!(verdicts[1].watched && verdicts[1].blocking.is_empty());
+        assert!(safe_push
+            .unavailable
+            .lock()
+            .unwrap()
+            .contains("watch target grp/missing @ main"));
+    }
+
+    #[tokio::test]
+    async fn unreadable_merge_requests_keep_pushed_refs_blocked() {
+        let gitlab_url = serve(&[
+            (
+                "/api/v4/projects/grp%2Fproj/pipelines",
+                "200 OK",
+                r#"[{"id": 1, "status": "running", "ref": "main"}]"#,
+            ),
+            (
+                "/api/v4/projects/grp%2Fproj/merge_requests",
+                "403 Forbidden",
+                r#"{"message": "403 Forbidden"}"#,
+            ),
+        ])
+        .await;
+        let safe_push = safe_push(
+            &gitlab_url,
+            Config {
+                ..Config::default()
+            },
+        );
+
+        let verdicts = safe_push
+            .check_blocking_refs("grp/proj", &["main".to_string()])
+            .await
+            .unwrap();
+        assert_eq!(verdicts.len(), 1);
+        assert!(matches!(
+            verdicts[0].blocking[..],
+            [(Some(_), BlockingReason::SimpleMode)]
+        ));
+        assert!(safe_push
+            .unavailable
+            .lock()
+            .unwrap()
+            .contains("merge request pipelines"));
+    }
+
+    #[tokio::test]
+    async fn reports_path_to_downstream_blocking_job() {
+        let gitlab_url = serve(&[
+            ("/api/v4/projects/grp%2Fproj/pipelines/1/jobs", "200 OK", "[]"),
+            (
+                "/api/v4/projects/grp%2Fproj/pipelines/1/bridges",
+                "200 OK",
+                r#"[
+                    {"id": 10, "name": "trigger:hidden", "stage": "deploy", "status": "success", "started_at": null, "created_at": "",
+                     "downstream_pipeline": {"id": 3, "project_id": 8, "status": "running"}},
+                    {"id": 11, "name": "trigger:child", "stage": "deploy", "status": "success", "started_at": null, "created_at": "",
+                     "downstream_pipeline": {"id": 2, "project_id": 7, "status": "running", "ref": "main"}}
+                ]"#,
+            ),
+            (
+                "/api/v4/projects/7",
+                "200 OK",
+                r#"{"path_with_namespace": "grp/child"}"#,
+            ),
+            (
+                "/api/v4/projects/grp%2Fchild/pipelines/2/jobs",
+                "200 OK",
+                r#"[{"id": 20, "name": "deploy:prod", "stage": "deploy", "status": "running", "started_at": null, "created_at": ""}]"#,
+            ),
+            ("/api/v4/projects/grp%2Fchild/pipelines/2/bridges", "200 OK", "[]"),
+        ])
+        .await;
+        let safe_push = safe_push(
+            &gitlab_url,
+            Config {
+                blocking_jobs: Some("deploy:*".to_string()),
+                ..Config::default()
+            },
+        );
+        let pipeline: Pipeline = serde_json::from_value(
+            serde_json::json!({"id": 1, "status": "running", "ref": "main"}),
+        )
+        .unwrap();
+
+        let reason = safe_push
+            .check_pipeline_blocking("grp/proj", "main", &pipeline)
+            .await
+            .unwrap();
+        match reason {
+            Some(BlockingReason::Downstream(path, project, r#ref, reason)) => {
+                assert_eq!(path, "trigger:child → grp/child #2");
+                assert_eq!((project.as_str(), r#ref.as_str()), ("grp/child", "main"));
+                assert!(matches!(
+                    *reason,
+                    BlockingReason::BlockingJobRunning(job, _, _) if job == "deploy:prod"
+                ));
+            }
+            _ => panic!("downstream blocking job not reported"),
+        }
+        // The project the token can't read is skipped
+        assert!(safe_push
+            .unavailable
+            .lock()
+            .unwrap()
+            .contains("downstream project 8"));
+    }
+
+    #[tokio::test]
+    async fn unreadable_downstream_pipeline_keeps_other_blockers() {
+        let gitlab_url = serve(&[
+            (
+                "/api/v4/projects/grp%2Fproj/pipelines",
+                "200 OK",
+                r#"[{"id": 1, "status": "running", "ref": "main"}, {"id": 2, "status": "running", "ref": "main"}]"#,
+            ),
+            (
+                "/api/v4/projects/grp%2Fproj/pipelines/1/jobs",
+                "200 OK",
+                r#"[{"id": 10, "name": "deploy", "stage": "deploy", "status": "running", "started_at": null, "created_at": ""}]"#,
+            ),
+            ("/api/v4/projects/grp%2Fproj/pipelines/1/bridges", "200 OK", "[]"),
+            ("/api/v4/projects/grp%2Fproj/pipelines/2/jobs", "200 OK", "[]"),
+            (
+                "/api/v4/projects/grp%2Fproj/pipelines/2/bridges",
+                "200 OK",
+                r#"[{"id": 20, "name": "trigger:other", "stage": "build", "status": "success", "started_at": null, "created_at": "",
+                     "downstream_pipeline": {"id": 5, "project_id": 9, "status": "running"}}]"#,
+            ),
+            (
+                "/api/v4/projects/9",
+                "200 OK",
+                r#"{"path_with_namespace": "grp/other"}"#,
+            ),
+            (
+                "/api/v4/projects/grp%2Fother/pipelines/5/jobs",
+                "403 Forbidden",
+                r#"{"message": "403 Forbidden"}"#,
+            ),
+            ("/api/v4/projects/grp%2Fother/pipelines/5/bridges", "200 OK", "[]"),
+            ("/api/v4/projects/grp%2Fproj/merge_requests", "200 OK", "[]"),
+        ])
+        .await;
+        let safe_push = safe_push(
+            &gitlab_url,
+            Config {
+                blocking_jobs: Some("deploy".to_string()),
+                ..Config::default()
+            },
+        );
+
+        let verdicts = safe_push
+            .check_blocking_refs("grp/proj", &["main".to_string()])
+            .await
+            .unwrap();
+        assert!(matches!(
+            &verdicts[0].blocking[..],
+            [(Some(pipeline), BlockingReason::BlockingJobRunning(job, _, _))]
+                if pipeline.id == 1 && job == "deploy"
+        ));
+        assert!(safe_push
+            .unavailable
+            .lock()
+            .unwrap()
+            .contains("downstream pipeline grp/other #5"));
+    }
+
+    #[test]
+    fn hook_checks_remotes_on_the_instance_only() {
+        let config = Config {
+            gitlab_url: Some("https://GitLab.example.com/gitlab".to_string()),
+            ..Config::default()
+        };
+        let remote = |url: &str| RemoteUrl::parse(url).unwrap();
+
+        assert!(on_instance(
+            &config,
+            &remote("git@gitlab.example.com:grp/proj.git")
+        ));
+        assert!(!on_instance(
+            &config,
+            &remote("git@github.com:grp/proj.git")
+        ));
+        let profiled = Config {
+            profile: Some("ssh.example.com".to_string()),
+            ..config
+        };
+        assert!(on_instance(
+            &profiled,
+            &remote("git@ssh.example.com:grp/proj.git")
+        ));
+    }
+
+    #[test]
+    fn finds_misplaced_options() {
+        let cases: [(&[&str], Option<&str>); 5] = [
+            (&["origin", "main", "--no-wait"], Some("--no-wait")),
+            (&["origin", "main", "--token=glpat-x"], Some("--token")),
+            (&["--force-with-lease", "origin", "main"], None),
+            (&["origin", "--", "--wait"], None),
+            // Not ours: help and version are clap's
+            (&["origin", "main", "--help"], None),
+        ];
+
+        for (args, expected) in cases {
+            let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+            assert_eq!(misplaced_option(&args).as_deref(), expected, "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn finds_next_link() {
+        let cases = [
+            (
+                r#"<https://gitlab.com/api/v4/projects/1/jobs?page=2&per_page=100>; rel="next""#,
+                Some("https://gitlab.com/api/v4/projects/1/jobs?page=2&per_page=100"),
+            ),
+            (
+                r#"<https://gitlab.com/api/v4/x?page=1>; rel="prev", <https://gitlab.com/api/v4/x?page=3>; rel="next", <https://gitlab.com/api/v4/x?page=9>; rel="last""#,
+                Some("https://gitlab.com/api/v4/x?page=3"),
+            ),
+            (
+                r#"<https://gitlab.com/api/v4/x?page=1>; rel="first", <https://gitlab.com/api/v4/x?page=9>; rel="last""#,
+                None,
+            ),
+            ("", None),
+        ];
+
+        for (link, expected) in cases {
+            assert_eq!(parse_link_next(link).as_deref(), expected, "{}", link);
+        }
+    }
+}
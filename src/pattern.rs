@@ -0,0 +1,235 @@
+use regex::Regex;
+use std::fmt;
+
+/// A job or stage name pattern from the configuration.
+///
+/// - `re:<regex>` is a regular expression, searched anywhere in the value
+/// - a value containing `*` or `?` is a glob over the whole value
+/// - anything else must match exactly
+///
+/// Exact matching is always tried first, so matrix job names such as
+/// `terraform: [eu-west-1]` work as written.
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    raw: String,
+    kind: Kind,
+}
+
+#[derive(Debug, Clone)]
+enum Kind {
+    Exact,
+    Glob,
+    Regex(Regex),
+}
+
+impl Pattern {
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let raw = raw.trim();
+        let kind = if let Some(expression) = raw.strip_prefix("re:") {
+            let regex = Regex::new(expression)
+                .map_err(|e| format!("invalid regex in pattern '{}': {}", raw, e))?;
+            Kind::Regex(regex)
+        } else if raw.contains(['*', '?']) {
+            Kind::Glob
+        } else {
+            Kind::Exact
+        };
+
+        Ok(Self {
+            raw: raw.to_string(),
+            kind,
+        })
+    }
+
+    /// The value matched, for patterns that are a plain name.
+    pub fn literal(&self) -> Option<&str> {
+        matches!(self.kind, Kind::Exact).then_some(self.raw.as_str())
+    }
+
+    pub fn matches(&self, value: &str) -> bool {
+        match &self.kind {
+            Kind::Exact => value == self.raw,
+            Kind::Glob => value == self.raw || glob_matches(&self.raw, value),
+            Kind::Regex(regex) => regex.is_match(value),
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.raw)
+    }
+}
+
+/// What a blocking job pattern is matched against: `tag:<pattern>` matches
+/// the runner tags of a job, `env:<pattern>` its environment name, and any
+/// other pattern its name.
+#[derive(Debug, Clone)]
+pub enum JobPattern {
+    Name(Pattern),
+    Tag(Pattern),
+    Environment(Pattern),
+}
+
+impl JobPattern {
+    pub fn parse(raw: &str) -> Result<Self, String> {
+        let raw = raw.trim();
+        if let Some(tag) = raw.strip_prefix("tag:") {
+            Ok(JobPattern::Tag(Pattern::parse(tag)?))
+        } else if let Some(environment) = raw.strip_prefix("env:") {
+            Ok(JobPattern::Environment(Pattern::parse(environment)?))
+        } else {
+            Ok(JobPattern::Name(Pattern::parse(raw)?))
+        }
+    }
+
+    pub fn matches(&self, name: &str, tags: &[String], environment: Option<&str>) -> bool {
+        match self {
+            JobPattern::Name(pattern) => pattern.matches(name),
+            JobPattern::Tag(pattern) => tags.iter().any(|tag| pattern.matches(tag)),
+            JobPattern::Environment(pattern) => environment.is_some_and(|env| pattern.matches(env)),
+        }
+    }
+}
+
+impl fmt::Display for JobPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JobPattern::Name(pattern) => write!(f, "{}", pattern),
+            JobPattern::Tag(pattern) => write!(f, "tag:{}", pattern),
+            JobPattern::Environment(pattern) => write!(f, "env:{}", pattern),
+        }
+    }
+}
+
+/// Split a comma-separated pattern list. Commas inside brackets or braces
+/// belong to the pattern (`deploy: [eu, prod]`, `re:x{1,3}`).
+pub fn split_list(list: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+
+    for c in list.chars() {
+        match c {
+            '[' | '{' | '(' => depth += 1,
+            ']' | '}' | ')' => depth = depth.saturating_sub(1),
+            ',' if depth == 0 => {
+                items.push(std::mem::take(&mut current));
+                continue;
+            }
+            _ => {}
+        }
+        current.push(c);
+    }
+    items.push(current);
+
+    items
+        .into_iter()
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// `*` matches any run of characters, `?` a single one.
+fn glob_matches(pattern: &str, value: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let value: Vec<char> = value.chars().collect();
+    let (mut p, mut v) = (0, 0);
+    // Position of the last `*` and the value index it was tried at
+    let mut backtrack = None;
+
+    while v < value.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                backtrack = Some((p, v));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                v += 1;
+            }
+            Some(&c) if c == value[v] => {
+                p += 1;
+                v += 1;
+            }
+            _ => match backtrack {
+                Some((star, tried)) => {
+                    p = star + 1;
+                    v = tried + 1;
+                    backtrack = Some((star, tried + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_exact_glob_and_regex_patterns() {
+        let cases = [
+            ("deploy:dev", "deploy:dev", true),
+            ("deploy:dev", "deploy:dev 1/3", false),
+            ("deploy:dev*", "deploy:dev 1/3", true),
+            ("deploy:*", "deploy:prod", true),
+            ("*:dev", "terraform:dev", true),
+            ("deploy:de?", "deploy:dev", true),
+            ("deploy:de?", "deploy:devel", false),
+            ("terraform: [eu-west-1]", "terraform: [eu-west-1]", true),
+            ("terraform: [eu-west-1]", "terraform: [us-east-1]", false),
+            ("terraform: *", "terraform: [us-east-1]", true),
+            ("re:^deploy:(dev|staging)", "deploy:staging 2/2", true),
+            ("re:^deploy:(dev|staging)$", "deploy:prod", false),
+            ("*", "", true),
+        ];
+
+        for (pattern, value, expected) in cases {
+            let parsed = Pattern::parse(pattern).unwrap();
+            assert_eq!(parsed.matches(value), expected, "{} ~ {}", pattern, value);
+        }
+    }
+
+    #[test]
+    fn matches_job_tags_and_environment() {
+        let tags = vec!["docker".to_string(), "prod-runner".to_string()];
+
+        let cases = [
+            ("tag:prod-*", true),
+            ("tag:shell", false),
+            ("env:production", true),
+            ("env:review/*", false),
+            ("build", true),
+        ];
+
+        for (pattern, expected) in cases {
+            let parsed = JobPattern::parse(pattern).unwrap();
+            assert_eq!(
+                parsed.matches("build", &tags, Some("production")),
+                expected,
+                "{}",
+                pattern
+            );
+        }
+        assert!(!JobPattern::parse("env:*")
+            .unwrap()
+            .matches("build", &tags, None));
+    }
+
+    #[test]
+    fn splits_lists_outside_brackets() {
+        assert_eq!(
+            split_list("terraform:dev, deploy: [eu, prod],re:x{1,3},,"),
+            vec!["terraform:dev", "deploy: [eu, prod]", "re:x{1,3}"]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_regex() {
+        assert!(Pattern::parse("re:deploy(").is_err());
+    }
+}
@@ -0,0 +1,104 @@
+/// Random spread applied to adaptive delays, as a fraction of the delay,
+/// so concurrent pushes don't poll in lockstep
+const JITTER: f64 = 0.1;
+
+/// How long to wait between two checks while a push is blocked.
+pub struct Polling {
+    /// Delay when nothing tells when the push may clear
+    pub interval: u64,
+    pub min: u64,
+    pub max: u64,
+}
+
+impl Polling {
+    /// Seconds until the next check.
+    ///
+    /// `until_known` is the time left before the last blocking condition
+    /// with a known end (freeze, blackout, post-block window): nothing can
+    /// clear before it, but a far end is still checked every `max` in case
+    /// it moves. `until_expected` is the time left before running
+    /// blocking jobs are expected to end; checks come at half of it, so
+    /// faster as the end nears and slower when a long stage just started.
+    /// Overdue jobs fall back to the interval. `noise` in `[-1, 1]` picks
+    /// the jitter.
+    pub fn next_delay(
+        &self,
+        until_known: Option<u64>,
+        until_expected: Option<u64>,
+        noise: f64,
+    ) -> u64 {
+        let adaptive = |base: u64| {
+            let jittered = base as f64 * (1.0 + JITTER * noise.clamp(-1.0, 1.0));
+            (jittered.round() as u64).clamp(self.min, self.max)
+        };
+
+        // Jitter only delays past the known end
+        let after = |known: u64| adaptive(known + 1).max(known + 1).min(self.max);
+
+        let expected = until_expected.map(|until| match until {
+            0 => adaptive(self.interval),
+            until => adaptive(until / 2),
+        });
+        match (until_known, expected) {
+            (Some(known), Some(expected)) => expected.max(after(known)),
+            (Some(known), None) => after(known),
+            (None, Some(expected)) => expected,
+            (None, None) => adaptive(self.interval),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POLLING: Polling = Polling {
+        interval: 30,
+        min: 5,
+        max: 300,
+    };
+
+    #[test]
+    fn adapts_to_the_expected_end() {
+        let cases = [
+            // Nothing known: the regular interval
+            (None, None, 30),
+            // A long stage just started: back off, within bounds
+            (None, Some(400), 200),
+            (None, Some(3600), 300),
+            // Close to the end: poll faster, within bounds
+            (None, Some(40), 20),
+            (None, Some(6), 5),
+            // Overdue
+            (None, Some(0), 30),
+            // Nothing clears before a known end
+            (Some(90), None, 91),
+            (Some(90), Some(40), 91),
+            (Some(2), Some(400), 200),
+            // A far end is still checked within bounds
+            (Some(3600), None, 300),
+            (Some(3600), Some(40), 300),
+        ];
+
+        for (known, expected, delay) in cases {
+            assert_eq!(
+                POLLING.next_delay(known, expected, 0.0),
+                delay,
+                "{:?} {:?}",
+                known,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        assert_eq!(POLLING.next_delay(None, None, 1.0), 33);
+        assert_eq!(POLLING.next_delay(None, None, -1.0), 27);
+        assert_eq!(POLLING.next_delay(None, Some(3600), 1.0), 300);
+        assert_eq!(POLLING.next_delay(None, Some(6), -1.0), 5);
+        assert_eq!(POLLING.next_delay(Some(90), None, 1.0), 100);
+        assert_eq!(POLLING.next_delay(Some(90), None, -1.0), 91);
+        assert_eq!(POLLING.next_delay(Some(0), None, -1.0), 5);
+    }
+}
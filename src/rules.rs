@@ -0,0 +1,469 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::pattern::{JobPattern, Pattern};
+use crate::status::Status;
+
+/// What a matching rule does to the push.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Action {
+    Block,
+    Warn,
+    Allow,
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::Block => "block",
+            Action::Warn => "warn",
+            Action::Allow => "allow",
+        })
+    }
+}
+
+/// A blocking rule as written in the configuration.
+///
+/// `ref` and `source` are matched against the pipeline. The job matchers
+/// (`stage`, `job`, `environment`, `status`, durations) need one job of
+/// the pipeline to match them all, or every job with `all_jobs`. A rule
+/// without job matchers applies to the whole pipeline.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    pub name: Option<String>,
+    pub action: Action,
+    #[serde(rename = "ref")]
+    pub ref_pattern: Option<String>,
+    /// Pipeline source, e.g. `push`, `schedule`, `merge_request_event`
+    pub source: Option<String>,
+    pub stage: Option<String>,
+    /// Job name pattern; `tag:` and `env:` prefixes work as in `blocking_jobs`
+    pub job: Option<String>,
+    pub environment: Option<String>,
+    /// Job statuses; unless `all_jobs` is set, those `job_statuses` treats
+    /// as active
+    pub status: Option<Vec<String>>,
+    /// The job has been running (or waiting) at least this many seconds
+    pub min_duration: Option<u64>,
+    /// The job has been running (or waiting) less than this many seconds
+    pub max_duration: Option<u64>,
+    pub all_jobs: Option<bool>,
+}
+
+/// The pipeline fields rules look at.
+pub struct PipelineFacts<'a> {
+    pub r#ref: &'a str,
+    pub source: Option<&'a str>,
+}
+
+/// The job fields rules look at.
+pub struct JobFacts<'a> {
+    pub name: &'a str,
+    pub stage: &'a str,
+    pub status: Status,
+    /// Whether the status counts as running under `job_statuses`
+    pub active: bool,
+    pub tags: &'a [String],
+    pub environment: Option<&'a str>,
+    pub seconds_running: Option<u64>,
+}
+
+/// Outcome of the first matching rule.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Verdict {
+    pub action: Action,
+    pub rule: String,
+    pub detail: String,
+}
+
+/// The verdict, if any rule matched, and why each rule did or didn't.
+pub struct Evaluation {
+    pub verdict: Option<Verdict>,
+    pub trace: Vec<String>,
+}
+
+/// Rules with their patterns compiled, in evaluation order.
+#[derive(Default)]
+pub struct RuleSet {
+    rules: Vec<CompiledRule>,
+}
+
+struct CompiledRule {
+    label: String,
+    action: Action,
+    ref_pattern: Option<Pattern>,
+    source: Option<Pattern>,
+    stage: Option<Pattern>,
+    job: Option<JobPattern>,
+    environment: Option<Pattern>,
+    status: Option<Vec<Status>>,
+    min_duration: Option<u64>,
+    max_duration: Option<u64>,
+    all_jobs: bool,
+}
+
+impl CompiledRule {
+    fn has_job_matchers(&self) -> bool {
+        self.stage.is_some()
+            || self.job.is_some()
+            || self.environment.is_some()
+            || self.status.is_some()
+            || self.min_duration.is_some()
+            || self.max_duration.is_some()
+            || self.all_jobs
+    }
+
+    fn matches_job(&self, job: &JobFacts) -> bool {
+        let status_matches = match &self.status {
+            Some(statuses) => statuses.contains(&job.status),
+            None => self.all_jobs || job.active,
+        };
+
+        status_matches
+            && self.stage.as_ref().is_none_or(|p| p.matches(job.stage))
+            && self
+                .job
+                .as_ref()
+                .is_none_or(|p| p.matches(job.name, job.tags, job.environment))
+            && self
+                .environment
+                .as_ref()
+                .is_none_or(|p| job.environment.is_some_and(|env| p.matches(env)))
+            && self
+                .min_duration
+                .is_none_or(|min| job.seconds_running.is_some_and(|s| s >= min))
+            && self
+                .max_duration
+                .is_none_or(|max| job.seconds_running.is_some_and(|s| s < max))
+    }
+
+    /// Why the rule matches, `Err` with why it doesn't.
+    fn evaluate(&self, pipeline: &PipelineFacts, jobs: &[JobFacts]) -> Result<String, String> {
+        if let Some(pattern) = &self.ref_pattern {
+            if !pattern.matches(pipeline.r#ref) {
+                return Err(format!(
+                    "ref '{}' does not match '{}'",
+                    pipeline.r#ref, pattern
+                ));
+            }
+        }
+        if let Some(pattern) = &self.source {
+            if !pipeline
+                .source
+                .is_some_and(|source| pattern.matches(source))
+            {
+                return Err(format!(
+                    "source '{}' does not match '{}'",
+                    pipeline.source.unwrap_or("unknown"),
+                    pattern
+                ));
+            }
+        }
+
+        if !self.has_job_matchers() {
+            return Ok(format!("pipeline on '{}'", pipeline.r#ref));
+        }
+
+        if self.all_jobs {
+            if jobs.is_empty() {
+                return Err("pipeline has no jobs".to_string());
+            }
+            return match jobs.iter().find(|job| !self.matches_job(job)) {
+                Some(job) => Err(format!("job '{}' does not match", job.name)),
+                None => Ok(format!("all {} jobs match", jobs.len())),
+            };
+        }
+
+        match jobs.iter().find(|job| self.matches_job(job)) {
+            Some(job) => Ok(describe_job(job)),
+            None => Err("no job matches".to_string()),
+        }
+    }
+}
+
+fn describe_job(job: &JobFacts) -> String {
+    let mut detail = format!(
+        "job '{}' in stage '{}' is {}",
+        job.name, job.stage, job.status
+    );
+    if let Some(seconds) = job.seconds_running {
+        detail.push_str(&format!(" ({}s)", seconds));
+    }
+    detail
+}
+
+impl RuleSet {
+    pub fn compile(rules: &[Rule]) -> Result<Self, String> {
+        let optional = |raw: &Option<String>| raw.as_deref().map(Pattern::parse).transpose();
+
+        let rules = rules
+            .iter()
+            .enumerate()
+            .map(|(idx, rule)| {
+                let label = rule
+                    .name
+                    .clone()
+                    .unwrap_or_else(|| format!("rule #{}", idx + 1));
+                let in_rule = |e: String| format!("{}: {}", label, e);
+
+                Ok(CompiledRule {
+                    action: rule.action,
+                    ref_pattern: optional(&rule.ref_pattern).map_err(in_rule)?,
+                    source: optional(&rule.source).map_err(in_rule)?,
+                    stage: optional(&rule.stage).map_err(in_rule)?,
+                    job: rule
+                        .job
+                        .as_deref()
+                        .map(JobPattern::parse)
+                        .transpose()
+                        .map_err(in_rule)?,
+                    environment: optional(&rule.environment).map_err(in_rule)?,
+                    status: rule
+                        .status
+                        .as_ref()
+                        .map(|statuses| {
+                            statuses
+                                .iter()
+                                .map(|status| Status::from_config(status))
+                                .collect::<Result<Vec<_>, _>>()
+                        })
+                        .transpose()
+                        .map_err(in_rule)?,
+                    min_duration: rule.min_duration,
+                    max_duration: rule.max_duration,
+                    all_jobs: rule.all_jobs.unwrap_or(false),
+                    label,
+                })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+
+        Ok(Self { rules })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// `label (action)` of each rule, for display
+    pub fn summary(&self) -> Vec<String> {
+        self.rules
+            .iter()
+            .map(|rule| format!("{} ({})", rule.label, rule.action))
+            .collect()
+    }
+
+    /// Apply the first rule matching the pipeline and its jobs. No match
+    /// means the pipeline doesn't block.
+    pub fn evaluate(&self, pipeline: &PipelineFacts, jobs: &[JobFacts]) -> Evaluation {
+        let mut trace = Vec::new();
+
+        for rule in &self.rules {
+            match rule.evaluate(pipeline, jobs) {
+                Ok(detail) => {
+                    trace.push(format!("{}: {} → {}", rule.label, detail, rule.action));
+                    return Evaluation {
+                        verdict: Some(Verdict {
+                            action: rule.action,
+                            rule: rule.label.clone(),
+                            detail,
+                        }),
+                        trace,
+                    };
+                }
+                Err(why) => trace.push(format!("{}: {}", rule.label, why)),
+            }
+        }
+
+        trace.push("no rule matched → allow".to_string());
+        Evaluation {
+            verdict: None,
+            trace,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(toml: &str) -> RuleSet {
+        #[derive(Deserialize)]
+        struct File {
+            rules: Vec<Rule>,
+        }
+        let file: File = toml::from_str(toml).unwrap();
+        RuleSet::compile(&file.rules).unwrap()
+    }
+
+    fn job<'a>(name: &'a str, stage: &'a str, status: &str, seconds: u64) -> JobFacts<'a> {
+        JobFacts {
+            name,
+            stage,
+            status: Status::from_config(status).unwrap(),
+            active: matches!(status, "running" | "pending"),
+            tags: &[],
+            environment: None,
+            seconds_running: Some(seconds),
+        }
+    }
+
+    const PUSH: PipelineFacts = PipelineFacts {
+        r#ref: "main",
+        source: Some("push"),
+    };
+
+    const EXAMPLE: &str = r#"
+        [[rules]]
+        name = "docs only"
+        action = "allow"
+        stage = "docs"
+        all_jobs = true
+
+        [[rules]]
+        action = "block"
+        job = "deploy:prod"
+
+        [[rules]]
+        action = "block"
+        stage = "terraform"
+        min_duration = 10
+    "#;
+
+    fn verdict(
+        rule_set: &RuleSet,
+        pipeline: &PipelineFacts,
+        jobs: &[JobFacts],
+    ) -> Option<(Action, String)> {
+        rule_set
+            .evaluate(pipeline, jobs)
+            .verdict
+            .map(|verdict| (verdict.action, verdict.rule))
+    }
+
+    #[test]
+    fn first_matching_rule_wins() {
+        let rule_set = rules(EXAMPLE);
+
+        let cases = [
+            (
+                vec![job("deploy:prod", "deploy", "running", 1)],
+                Some((Action::Block, "rule #2")),
+            ),
+            (
+                vec![job("plan", "terraform", "running", 12)],
+                Some((Action::Block, "rule #3")),
+            ),
+            (vec![job("plan", "terraform", "running", 3)], None),
+            (vec![job("deploy:prod", "deploy", "success", 30)], None),
+            (
+                vec![
+                    job("pages", "docs", "running", 60),
+                    job("lint", "docs", "success", 60),
+                ],
+                Some((Action::Allow, "docs only")),
+            ),
+            (
+                vec![
+                    job("pages", "docs", "success", 60),
+                    job("deploy:prod", "deploy", "running", 1),
+                ],
+                Some((Action::Block, "rule #2")),
+            ),
+            (vec![], None),
+        ];
+
+        for (jobs, expected) in cases {
+            let expected = expected.map(|(action, rule)| (action, rule.to_string()));
+            let names: Vec<&str> = jobs.iter().map(|job| job.name).collect();
+            assert_eq!(verdict(&rule_set, &PUSH, &jobs), expected, "{:?}", names);
+        }
+    }
+
+    #[test]
+    fn matches_pipeline_ref_and_source() {
+        let rule_set = rules(
+            r#"
+            [[rules]]
+            action = "allow"
+            source = "schedule"
+
+            [[rules]]
+            action = "warn"
+            ref = "release/*"
+            job = "deploy:*"
+            "#,
+        );
+        let jobs = [job("deploy:eu", "deploy", "pending", 0)];
+
+        let schedule = PipelineFacts {
+            r#ref: "release/1.0",
+            source: Some("schedule"),
+        };
+        let release = PipelineFacts {
+            r#ref: "release/1.0",
+            source: Some("push"),
+        };
+
+        assert_eq!(
+            verdict(&rule_set, &schedule, &jobs),
+            Some((Action::Allow, "rule #1".to_string()))
+        );
+        assert_eq!(
+            verdict(&rule_set, &release, &jobs),
+            Some((Action::Warn, "rule #2".to_string()))
+        );
+        assert_eq!(verdict(&rule_set, &PUSH, &jobs), None);
+    }
+
+    #[test]
+    fn explains_every_rule_tried() {
+        let evaluation = rules(EXAMPLE).evaluate(&PUSH, &[job("plan", "terraform", "running", 12)]);
+
+        assert_eq!(
+            evaluation.trace,
+            vec![
+                "docs only: job 'plan' does not match",
+                "rule #2: no job matches",
+                "rule #3: job 'plan' in stage 'terraform' is running (12s) → block",
+            ]
+        );
+    }
+
+    #[test]
+    fn matches_configured_job_statuses() {
+        let rule_set = rules(
+            r#"
+            [[rules]]
+            action = "block"
+            job = "deploy"
+            status = ["manual", "other:paused"]
+            "#,
+        );
+        let cases = [("manual", true), ("other:paused", true), ("running", false)];
+        for (status, blocks) in cases {
+            let jobs = [job("deploy", "deploy", status, 0)];
+            assert_eq!(
+                verdict(&rule_set, &PUSH, &jobs).is_some(),
+                blocks,
+                "{}",
+                status
+            );
+        }
+
+        let typo: Rule = toml::from_str(
+            r#"
+            action = "block"
+            status = ["runing"]
+            "#,
+        )
+        .unwrap();
+        let error = RuleSet::compile(&[typo]).err().unwrap();
+        assert!(
+            error.starts_with("rule #1: unknown status `runing` (did you mean `running`?)"),
+            "{}",
+            error
+        );
+    }
+}
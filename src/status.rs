@@ -0,0 +1,204 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// A pipeline or job status. Statuses this version doesn't know about are
+/// kept as they are, so a new GitLab status never fails a check.
+#[derive(Deserialize, Clone, Debug, PartialEq, Eq, Hash)]
+#[serde(from = "String")]
+pub enum Status {
+    Created,
+    WaitingForResource,
+    Preparing,
+    Pending,
+    Running,
+    Success,
+    Failed,
+    Canceling,
+    Canceled,
+    Skipped,
+    Manual,
+    Scheduled,
+    Unknown(String),
+}
+
+impl Status {
+    /// Every status GitLab documents, in pipeline lifecycle order.
+    pub const KNOWN: [Status; 12] = [
+        Status::Created,
+        Status::WaitingForResource,
+        Status::Preparing,
+        Status::Pending,
+        Status::Running,
+        Status::Success,
+        Status::Failed,
+        Status::Canceling,
+        Status::Canceled,
+        Status::Skipped,
+        Status::Manual,
+        Status::Scheduled,
+    ];
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            Status::Created => "created",
+            Status::WaitingForResource => "waiting_for_resource",
+            Status::Preparing => "preparing",
+            Status::Pending => "pending",
+            Status::Running => "running",
+            Status::Success => "success",
+            Status::Failed => "failed",
+            Status::Canceling => "canceling",
+            Status::Canceled => "canceled",
+            Status::Skipped => "skipped",
+            Status::Manual => "manual",
+            Status::Scheduled => "scheduled",
+            Status::Unknown(status) => status,
+        }
+    }
+
+    /// How pipelines in this status are treated unless configured.
+    ///
+    /// Pipelines waiting on a manual job are inspected for running
+    /// downstream pipelines but don't count as running themselves.
+    pub fn default_pipeline_policy(&self) -> StatusPolicy {
+        match self {
+            Status::Created
+            | Status::WaitingForResource
+            | Status::Preparing
+            | Status::Pending
+            | Status::Running
+            | Status::Scheduled => StatusPolicy::Active,
+            Status::Manual => StatusPolicy::Check,
+            _ => StatusPolicy::Ignore,
+        }
+    }
+
+    /// How jobs in this status are treated unless configured: only jobs
+    /// that hold or wait for a runner count as running.
+    pub fn default_job_policy(&self) -> StatusPolicy {
+        match self {
+            Status::WaitingForResource | Status::Preparing | Status::Pending | Status::Running => {
+                StatusPolicy::Active
+            }
+            _ => StatusPolicy::Ignore,
+        }
+    }
+
+    /// A status named in configuration. Statuses this version doesn't know
+    /// must be written `other:<status>`, so a typo is never taken for one.
+    pub fn from_config(name: &str) -> Result<Status, String> {
+        if let Some(other) = name.strip_prefix("other:") {
+            return Ok(Status::from(other));
+        }
+
+        if let Some(status) = Status::KNOWN
+            .into_iter()
+            .find(|known| known.as_str() == name)
+        {
+            return Ok(status);
+        }
+        let suggestion = Status::KNOWN
+            .iter()
+            .map(|known| (strsim::levenshtein(name, known.as_str()), known))
+            .filter(|(distance, _)| *distance <= 3)
+            .min_by_key(|(distance, _)| *distance)
+            .map(|(_, known)| format!(" (did you mean `{}`?)", known))
+            .unwrap_or_default();
+        Err(format!(
+            "unknown status `{}`{}, write `other:{}` for a status this version doesn't know",
+            name, suggestion, name
+        ))
+    }
+}
+
+impl From<String> for Status {
+    fn from(status: String) -> Self {
+        Status::KNOWN
+            .into_iter()
+            .find(|known| known.as_str() == status)
+            .unwrap_or(Status::Unknown(status))
+    }
+}
+
+impl From<&str> for Status {
+    fn from(status: &str) -> Self {
+        Status::from(status.to_string())
+    }
+}
+
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// What a status means for the check.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StatusPolicy {
+    /// Counts as running: the pipeline blocks in simple mode and is
+    /// inspected, the job counts as running for blocking jobs and stages
+    Active,
+    /// Pipelines only: inspected for blocking jobs and downstream
+    /// pipelines, without counting as running by themselves
+    Check,
+    Ignore,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_known_and_unknown_statuses() {
+        for status in Status::KNOWN {
+            assert_eq!(Status::from(status.as_str()), status);
+        }
+
+        let statuses: Vec<Status> =
+            serde_json::from_str(r#"["waiting_for_resource", "teleporting"]"#).unwrap();
+        assert_eq!(
+            statuses,
+            vec![
+                Status::WaitingForResource,
+                Status::Unknown("teleporting".to_string())
+            ]
+        );
+        assert_eq!(statuses[1].to_string(), "teleporting");
+    }
+
+    #[test]
+    fn reads_configured_statuses() {
+        assert_eq!(Status::from_config("running"), Ok(Status::Running));
+        assert_eq!(
+            Status::from_config("other:teleporting"),
+            Ok(Status::Unknown("teleporting".to_string()))
+        );
+        assert_eq!(Status::from_config("other:manual"), Ok(Status::Manual));
+
+        let error = Status::from_config("runing").unwrap_err();
+        assert!(error.contains("did you mean `running`?"), "{}", error);
+        assert!(Status::from_config("teleporting").is_err());
+    }
+
+    #[test]
+    fn default_policies() {
+        assert_eq!(
+            Status::Scheduled.default_pipeline_policy(),
+            StatusPolicy::Active
+        );
+        assert_eq!(
+            Status::Manual.default_pipeline_policy(),
+            StatusPolicy::Check
+        );
+        assert_eq!(
+            Status::Unknown("teleporting".to_string()).default_pipeline_policy(),
+            StatusPolicy::Ignore
+        );
+        assert_eq!(
+            Status::WaitingForResource.default_job_policy(),
+            StatusPolicy::Active
+        );
+        assert_eq!(Status::Created.default_job_policy(), StatusPolicy::Ignore);
+    }
+}